@@ -1,32 +1,293 @@
-use crate::config::BarConfig;
+use crate::config::{self, BarConfig, BarModule, BarPosition};
 use rusttype::{point, Font, Scale};
 use x11rb::connection::Connection;
 use x11rb::protocol::xproto::{
-    AtomEnum, ConnectionExt, CreateGCAux, CreateWindowAux, EventMask, Gcontext,
-    ImageFormat, Rectangle, Screen, Window, WindowClass,
+    Atom, AtomEnum, ChangeGCAux, ChangeWindowAttributesAux, ConfigureWindowAux, ConnectionExt, CreateGCAux, CreateWindowAux, EventMask, Gcontext,
+    ImageFormat, ImageOrder, Rectangle, Screen, Visualid, Window, WindowClass,
 };
 use std::fs;
+use std::io::{BufRead, BufReader};
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
 use std::process::Command;
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
 use std::time::{Instant, Duration};
 
 // --- CONSTANTS ---
+// Narrowest a workspace cell gets; wider labels widen their own cell
 const CELL_WIDTH: i16 = 30;
+// Space on each side of a workspace label
+const CELL_PADDING: i16 = 8;
+
+// Tried in order when the configured font can't be loaded
+const FALLBACK_FONTS: &[&str] = &[
+    "/usr/share/fonts/TTF/DejaVuSansMono.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSansMono.ttf",
+    "/usr/share/fonts/TTF/LiberationMono-Regular.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationMono-Regular.ttf",
+    "/usr/share/fonts/liberation/LiberationMono-Regular.ttf",
+    "/usr/share/fonts/noto/NotoSansMono-Regular.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSansMono-Regular.ttf",
+];
+
+// Loads a TTF/OTF file. 'try_from_vec' takes ownership of the bytes,
+// so the Font can live as long as the Bar without borrowing.
+fn load_font(path: &str) -> Option<Font<'static>> {
+    match fs::read(path) {
+        Ok(data) => {
+            let font = Font::try_from_vec(data);
+            if font.is_none() {
+                log::error!("Failed to parse font file: {}", path);
+            }
+            font
+        }
+        Err(e) => {
+            log::error!("Could not read font file '{}': {}", path, e);
+            None
+        }
+    }
+}
+
+// Splits module output into runs of (text, fg, bg) using polybar-style markup:
+// %{F#RRGGBB} / %{B#RRGGBB} set the foreground / background, %{F-} / %{B-} reset
+// them to the module's colors. Unknown tags are dropped; text without markup is a
+// single run.
+fn parse_markup(text: &str, fg: u32, bg: u32) -> Vec<(String, u32, u32)> {
+    let mut runs = Vec::new();
+    let (mut cur_fg, mut cur_bg) = (fg, bg);
+    let mut rest = text;
+
+    while let Some(start) = rest.find("%{") {
+        let Some(len) = rest[start..].find('}') else { break };
+        if start > 0 {
+            runs.push((rest[..start].to_string(), cur_fg, cur_bg));
+        }
+        match &rest[start + 2..start + len] {
+            "F-" => cur_fg = fg,
+            "B-" => cur_bg = bg,
+            tag => {
+                if let Some(color) = tag.strip_prefix('F').and_then(config::parse_color) {
+                    cur_fg = color;
+                } else if let Some(color) = tag.strip_prefix('B').and_then(config::parse_color) {
+                    cur_bg = color;
+                }
+            }
+        }
+        rest = &rest[start + len + 1..];
+    }
+    if !rest.is_empty() {
+        runs.push((rest.to_string(), cur_fg, cur_bg));
+    }
+    runs
+}
+
+// How the root visual lays out a pixel, so text images can be built for any
+// TrueColor depth instead of assuming 32-bit BGRA at depth 24
+struct PixelFormat {
+    depth: u8,
+    bytes_per_pixel: usize,
+    scanline_pad: usize, // In bytes
+    red_mask: u32,
+    green_mask: u32,
+    blue_mask: u32,
+    msb_first: bool,
+}
+
+impl PixelFormat {
+    fn new<C: Connection>(conn: &C, screen: &Screen) -> Self {
+        let setup = conn.setup();
+        let visual = screen.allowed_depths.iter()
+            .flat_map(|d| &d.visuals)
+            .find(|v| v.visual_id == screen.root_visual);
+        let format = setup.pixmap_formats.iter().find(|f| f.depth == screen.root_depth);
+
+        // Fall back to the usual 24-bit layout if the server didn't describe it
+        let (red_mask, green_mask, blue_mask) = visual
+            .map_or((0xFF0000, 0x00FF00, 0x0000FF), |v| (v.red_mask, v.green_mask, v.blue_mask));
+        let (bits_per_pixel, scanline_pad) = format
+            .map_or((32, 32), |f| (f.bits_per_pixel, f.scanline_pad));
+
+        Self {
+            depth: screen.root_depth,
+            bytes_per_pixel: (bits_per_pixel as usize).div_ceil(8),
+            scanline_pad: (scanline_pad as usize / 8).max(1),
+            red_mask,
+            green_mask,
+            blue_mask,
+            msb_first: setup.image_byte_order == ImageOrder::MSB_FIRST,
+        }
+    }
+
+    // Converts a 0xRRGGBB color to a pixel value for this visual
+    fn pixel(&self, rgb: u32) -> u32 {
+        let channel = |value: u32, mask: u32| -> u32 {
+            if mask == 0 { return 0; }
+            let shift = mask.trailing_zeros();
+            let max = mask >> shift;
+            ((value * max + 127) / 255) << shift
+        };
+        channel((rgb >> 16) & 0xFF, self.red_mask)
+            | channel((rgb >> 8) & 0xFF, self.green_mask)
+            | channel(rgb & 0xFF, self.blue_mask)
+    }
+
+    // Packs 0xRRGGBB pixels into Z-pixmap rows, padded to the scanline unit
+    fn encode(&self, pixels: &[u32], width: usize) -> Vec<u8> {
+        let row_len = (width * self.bytes_per_pixel).div_ceil(self.scanline_pad) * self.scanline_pad;
+        let mut data = vec![0u8; row_len * pixels.len().div_ceil(width.max(1))];
+
+        for (i, &rgb) in pixels.iter().enumerate() {
+            let start = (i / width) * row_len + (i % width) * self.bytes_per_pixel;
+            let value = self.pixel(rgb);
+            for byte in 0..self.bytes_per_pixel {
+                let shift = if self.msb_first { (self.bytes_per_pixel - 1 - byte) * 8 } else { byte * 8 };
+                data[start + byte] = (value >> shift) as u8;
+            }
+        }
+        data
+    }
+}
 
 pub struct ModuleState {
     pub last_output: String,
     pub last_update: Instant,
+    // Latest line read from the module's FIFO, if it is a FIFO module
+    pub fifo_line: Option<Arc<Mutex<String>>>,
+    // Per-module colors, overriding the bar's
+    pub fg_color: Option<u32>,
+    pub bg_color: Option<u32>,
+}
+
+impl ModuleState {
+    fn new(module: &BarModule) -> Self {
+        let fifo_line = module.fifo.as_ref().map(|path| {
+            let latest = Arc::new(Mutex::new(String::new()));
+            spawn_fifo_reader(path.clone(), Arc::downgrade(&latest));
+            latest
+        });
+        Self {
+            last_output: String::new(),
+            last_update: Instant::now() - Duration::from_secs(100),
+            fifo_line,
+            fg_color: module.fg.as_deref().and_then(config::parse_color),
+            bg_color: module.bg.as_deref().and_then(config::parse_color),
+        }
+    }
+}
+
+// Text of a workspace cell. Workspaces without an icon show their number.
+fn workspace_label(config: &BarConfig, index: usize, active: bool) -> String {
+    if config.workspace_style == "Squares" {
+        return if active { "[x]" } else { "[ ]" }.to_string();
+    }
+    config.workspace_icons.get(index).cloned().unwrap_or_else(|| (index + 1).to_string())
+}
+
+// x-range of each workspace cell, laid out from the left edge. A cell is its label's
+// width plus padding, but never narrower than CELL_WIDTH.
+fn cell_ranges(text_widths: &[i16]) -> Vec<(i16, i16)> {
+    let mut x = 0;
+    text_widths.iter().map(|&text_w| {
+        let start = x;
+        x += CELL_WIDTH.max(text_w + 2 * CELL_PADDING);
+        (start, x)
+    }).collect()
+}
+
+// Index of the range holding x
+fn range_at(ranges: &[(i16, i16)], x: i16) -> Option<usize> {
+    ranges.iter().position(|(start, end)| (*start..*end).contains(&x))
+}
+
+fn bar_y(position: BarPosition, screen_height: u16, height: u16) -> i16 {
+    match position {
+        BarPosition::Top => 0,
+        BarPosition::Bottom => (screen_height - height) as i16,
+    }
+}
+
+// Whether a module from a reloaded config is the same one as before, so its state and
+// FIFO reader carry over. FIFO modules are matched by path, others by command and interval.
+fn same_module(old: &BarModule, new: &BarModule) -> bool {
+    match (&old.fifo, &new.fifo) {
+        (Some(old_path), Some(new_path)) => old_path == new_path,
+        (None, None) => old.command == new.command && old.interval == new.interval,
+        _ => false,
+    }
+}
+
+// Reads lines from a named pipe in the background, keeping only the most recent one.
+// The FIFO is created if it doesn't exist yet. The reader stops once the module owning
+// `latest` is dropped, at its next line or EOF.
+fn spawn_fifo_reader(path: String, latest: Weak<Mutex<String>>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        if !Path::new(&path).exists() {
+            match Command::new("mkfifo").arg(&path).status() {
+                Ok(status) if status.success() => {}
+                _ => {
+                    log::error!("Could not create FIFO '{}'", path);
+                    return;
+                }
+            }
+        }
+        if !fs::metadata(&path).is_ok_and(|m| m.file_type().is_fifo()) {
+            log::error!("Bar module path '{}' is not a FIFO", path);
+            return;
+        }
+
+        // Opening blocks until a writer connects; EOF means the writer closed it
+        while latest.strong_count() > 0 {
+            match fs::File::open(&path) {
+                Ok(file) => {
+                    for line in BufReader::new(file).lines().map_while(Result::ok) {
+                        let Some(latest) = latest.upgrade() else { return };
+                        if let Ok(mut latest) = latest.lock() {
+                            *latest = line.trim().to_string();
+                        }
+                    }
+                }
+                Err(e) => {
+                    log::error!("Could not open FIFO '{}': {}", path, e);
+                    return;
+                }
+            }
+        }
+    })
+}
+
+// Unblocks a FIFO reader waiting in open() for a writer, so it notices its module is
+// gone. Linux lets a FIFO be opened read-write without blocking, and closing it again
+// hands the reader EOF.
+fn wake_fifo_reader(path: &str) {
+    let _ = fs::OpenOptions::new().read(true).write(true).open(path);
 }
 
 pub struct Bar {
     pub window: Window,
     gc: Gcontext,
+    root: Window,
+    visual: Visualid,
     width: u16,
     height: u16,
+    screen_height: u16,
     config: BarConfig,
+    fg_color: u32,
+    bg_color: u32,
+    pixel_format: PixelFormat,
+    pub show_title: bool,
     module_states: Vec<ModuleState>,
+    // x-range of each workspace cell as last drawn, for click-to-switch
+    workspace_ranges: Vec<(i16, i16)>,
+    // x-range of the layout symbol as last drawn, for scroll-to-cycle
+    layout_range: (i16, i16),
+    // x-range of each tab as last drawn, for click-to-focus
+    tab_ranges: Vec<(i16, i16, Window)>,
+    net_wm_name: Atom,
+    utf8_string: Atom,
     // Modern Font Data
     font: Option<Font<'static>>,
-    font_data: Vec<u8>, // Keep the bytes in memory
 }
 
 impl Bar {
@@ -34,38 +295,37 @@ impl Bar {
         conn: &C,
         screen: &Screen,
         config: BarConfig,
+        position: BarPosition,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         let window = conn.generate_id()?;
         let gc = conn.generate_id()?;
         let height = 24; // Slightly taller for modern fonts
         let width = screen.width_in_pixels;
 
-        // 1. Load Font from File
-        let font_path = &config.font;
-        let mut font = None;
-        let mut font_data = Vec::new();
-
-        // Try to load the TTF/OTF file
-        match fs::read(font_path) {
-            Ok(data) => {
-                font_data = data;
-                // We must use 'unsafe' to cast the reference lifetime, or clone the data.
-                // Since 'Bar' owns 'font_data', it's safe to reference it as long as Bar exists.
-                // To keep it safe Rust, we construct the Font from the slice every time OR 
-                // use 'try_from_vec' if available, but rusttype usually takes a slice.
-                // Hack: We re-parse the font from the owned vector.
-                if let Some(f) = Font::try_from_vec(font_data.clone()) {
-                     font = Some(f);
-                } else {
-                    log::error!("Failed to parse font file: {}", font_path);
-                }
-            },
-            Err(e) => log::error!("Could not read font file '{}': {}", font_path, e),
+        // 1. Load Font from File, falling back to common system fonts
+        let font = load_font(&config.font).or_else(|| {
+            FALLBACK_FONTS.iter().filter(|path| Path::new(path).exists()).find_map(|path| {
+                let font = load_font(path)?;
+                log::warn!("Using fallback font '{}'", path);
+                Some(font)
+            })
+        });
+        if font.is_none() {
+            log::error!("No usable font found, bar text will not be drawn");
         }
 
+        // Colors from config, defaulting to white on black
+        let fg_color = config.foreground.as_deref().and_then(config::parse_color).unwrap_or(0xFFFFFF);
+        let bg_color = config.background.as_deref().and_then(config::parse_color).unwrap_or(0x000000);
+
+        let pixel_format = PixelFormat::new(conn, screen);
+
+        let net_wm_name = conn.intern_atom(false, b"_NET_WM_NAME")?.reply()?.atom;
+        let utf8_string = conn.intern_atom(false, b"UTF8_STRING")?.reply()?.atom;
+
         // 2. Create Window
         let win_aux = CreateWindowAux::new()
-            .background_pixel(screen.black_pixel)
+            .background_pixel(pixel_format.pixel(bg_color))
             .override_redirect(1)
             .event_mask(EventMask::EXPOSURE | EventMask::BUTTON_PRESS);
 
@@ -74,7 +334,7 @@ impl Bar {
             window,
             screen.root,
             0,
-            0,
+            bar_y(position, screen.height_in_pixels, height),
             width,
             height,
             0,
@@ -85,69 +345,132 @@ impl Bar {
 
         // 3. Create GC
         let gc_aux = CreateGCAux::new()
-            .foreground(screen.white_pixel)
-            .background(screen.black_pixel)
+            .foreground(pixel_format.pixel(fg_color))
+            .background(pixel_format.pixel(bg_color))
             .graphics_exposures(0);
 
         conn.create_gc(gc, window, &gc_aux)?;
         conn.map_window(window)?;
 
-        let module_states = config.modules.iter().map(|_| ModuleState {
-            last_output: String::new(),
-            last_update: Instant::now() - Duration::from_secs(100),
-        }).collect();
+        let module_states = config.modules.iter().map(ModuleState::new).collect();
 
         Ok(Self {
             window,
             gc,
+            root: screen.root,
+            visual: screen.root_visual,
             width,
             height,
+            screen_height: screen.height_in_pixels,
+            show_title: config.show_title,
             config,
+            fg_color,
+            bg_color,
+            pixel_format,
             module_states,
+            workspace_ranges: Vec::new(),
+            layout_range: (0, 0),
+            tab_ranges: Vec::new(),
+            net_wm_name,
+            utf8_string,
             font,
-            font_data,
         })
     }
 
+    // Applies a reloaded bar config to the existing window. A module whose command and
+    // FIFO are unchanged keeps its state and FIFO reader; new modules start fresh.
+    pub fn reload<C: Connection>(&mut self, conn: &C, config: BarConfig) -> Result<(), Box<dyn std::error::Error>> {
+        if config.font != self.config.font {
+            match load_font(&config.font) {
+                Some(font) => self.font = Some(font),
+                None => log::warn!("Could not load font '{}', keeping the current one", config.font),
+            }
+        }
+
+        self.fg_color = config.foreground.as_deref().and_then(config::parse_color).unwrap_or(0xFFFFFF);
+        self.bg_color = config.background.as_deref().and_then(config::parse_color).unwrap_or(0x000000);
+        let bg_pixel = self.pixel_format.pixel(self.bg_color);
+        let fg_pixel = self.pixel_format.pixel(self.fg_color);
+        conn.change_window_attributes(self.window, &ChangeWindowAttributesAux::new().background_pixel(bg_pixel))?;
+        conn.change_gc(self.gc, &ChangeGCAux::new().foreground(fg_pixel).background(bg_pixel))?;
+
+        let mut old: Vec<(BarModule, ModuleState)> =
+            self.config.modules.drain(..).zip(self.module_states.drain(..)).collect();
+        // Unchanged modules keep their output, schedule and FIFO reader
+        self.module_states = config.modules.iter().map(|module| {
+            match old.iter().position(|(m, _)| same_module(m, module)) {
+                Some(pos) => {
+                    let (_, mut state) = old.remove(pos);
+                    state.fg_color = module.fg.as_deref().and_then(config::parse_color);
+                    state.bg_color = module.bg.as_deref().and_then(config::parse_color);
+                    state
+                }
+                None => ModuleState::new(module),
+            }
+        }).collect();
+        // Dropping a removed FIFO module's state tells its reader to stop
+        for (module, state) in old {
+            drop(state);
+            if let Some(path) = &module.fifo {
+                wake_fifo_reader(path);
+            }
+        }
+
+        self.show_title = config.show_title;
+        self.config = config;
+        Ok(())
+    }
+
+    // Height of the bar window, which is the space tiled windows leave free for it
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    // Moves the bar to the top or bottom edge of the screen
+    pub fn set_position<C: Connection>(&self, conn: &C, position: BarPosition) -> Result<(), Box<dyn std::error::Error>> {
+        let y = bar_y(position, self.screen_height, self.height);
+        conn.configure_window(self.window, &ConfigureWindowAux::new().y(y as i32))?;
+        Ok(())
+    }
+
     pub fn draw<C: Connection>(
         &mut self,
         conn: &C,
         active_idx: usize,
-        _total_workspaces: usize,
+        total_workspaces: usize,
         layout_name: &str,
         focused_window: Option<Window>,
+        tabs: &[Window],
     ) -> Result<(), Box<dyn std::error::Error>> {
         // Clear Bar
         conn.clear_area(false, self.window, 0, 0, self.width, self.height)?;
 
-        let mut x_offset = 0i16;
-        let bg_color = 0x000000; // Black
-        let fg_color = 0xFFFFFF; // White
-        let active_bg = 0xFFFFFF; // White
-        let active_fg = 0x000000; // Black
+        let bg_color = self.bg_color;
+        let fg_color = self.fg_color;
+        // Active workspace cell is drawn inverted
+        let active_bg = self.fg_color;
+        let active_fg = self.bg_color;
 
         // 1. Draw Workspaces
-        for (i, icon) in self.config.workspace_icons.iter().enumerate() {
+        let labels: Vec<String> = (0..total_workspaces)
+            .map(|i| workspace_label(&self.config, i, i == active_idx))
+            .collect();
+        let text_widths: Vec<i16> = labels.iter().map(|l| self.measure_text(l) as i16).collect();
+        self.workspace_ranges = cell_ranges(&text_widths);
+        for (i, display_text) in labels.iter().enumerate() {
             let is_active = i == active_idx;
-            let cell_x = i as i16 * CELL_WIDTH;
-            
-            // Text to draw
-            let display_text = if self.config.workspace_style == "Squares" {
-                if is_active { "[x]" } else { "[ ]" }
-            } else {
-                icon.as_str()
-            };
+            let (cell_x, cell_end) = self.workspace_ranges[i];
+            let cell_w = cell_end - cell_x;
 
-            // Measure Text
-            let text_w = self.measure_text(display_text) as i16;
-            let center_x = cell_x + (CELL_WIDTH - text_w) / 2;
+            // Center the text in its cell
+            let center_x = cell_x + (cell_w - text_widths[i]) / 2;
             // Vertically center: (Bar Height / 2) + (Font Height / 4 approx)
             let center_y = (self.height as f32 / 2.0) + 4.0; 
 
             if is_active {
                 // Draw Active Background
                 conn.poly_fill_rectangle(self.window, self.gc, &[Rectangle{
-                    x: cell_x, y: 0, width: CELL_WIDTH as u16, height: self.height
+                    x: cell_x, y: 0, width: cell_w as u16, height: self.height
                 }])?;
                 
                 // Draw Text (Inverted)
@@ -158,28 +481,15 @@ impl Bar {
             }
         }
 
-        x_offset = (self.config.workspace_icons.len() as i16 * CELL_WIDTH) + 10;
+        let mut x_offset = self.workspace_ranges.last().map_or(0, |&(_, end)| end) + 10;
 
         // 2. Draw Layout Symbol
         self.draw_text_modern(conn, x_offset, ((self.height/2)+4) as i16, layout_name, fg_color, bg_color)?;
         let layout_w = self.measure_text(layout_name) as i16;
+        self.layout_range = (x_offset, x_offset + layout_w);
         x_offset += layout_w + 15;
 
-        // 3. Draw Window Title
-        if let Some(win) = focused_window {
-            let wm_name = conn.get_property(false, win, AtomEnum::WM_NAME, AtomEnum::STRING, 0, 1024)?.reply();
-            if let Ok(prop) = wm_name {
-                 let title = String::from_utf8_lossy(&prop.value).to_string();
-                 let title_w = self.measure_text(&title) as i16;
-                 
-                 let center_x = (self.width as i16 / 2) - (title_w / 2);
-                 if center_x > x_offset {
-                     self.draw_text_modern(conn, center_x, ((self.height/2)+4) as i16, &title, fg_color, bg_color)?;
-                 }
-            }
-        }
-
-        // 4. Draw Modules
+        // 3. Draw Modules (right to left, so the tabs know how much room is left)
         let mut right_x = self.width as i16 - 10;
 
         // A. Time
@@ -189,32 +499,103 @@ impl Bar {
         self.draw_text_modern(conn, right_x, ((self.height/2)+4) as i16, &time_str, fg_color, bg_color)?;
         right_x -= 15;
 
-        // B. Update & Draw Modules
-        for i in 0..self.config.modules.len() {
-             // Update
-             let interval = self.config.modules[i].interval;
-             if self.module_states[i].last_update.elapsed() > Duration::from_secs(interval) {
-                let cmd = self.config.modules[i].command.clone();
-                if let Ok(output) = Command::new("sh").arg("-c").arg(&cmd).output() {
-                    let s = String::from_utf8_lossy(&output.stdout).trim().to_string();
-                    self.module_states[i].last_output = s;
-                }
-                self.module_states[i].last_update = Instant::now();
-             }
-
-             // Draw
-             let output = &self.module_states[i].last_output;
+        // B. Draw Modules (outputs are refreshed by update_modules)
+        for state in &self.module_states {
+             let output = &state.last_output;
              if !output.is_empty() {
-                let w = self.measure_text(output) as i16;
+                let module_fg = state.fg_color.unwrap_or(fg_color);
+                let module_bg = state.bg_color.unwrap_or(bg_color);
+                let runs = parse_markup(output, module_fg, module_bg);
+                let w: i16 = runs.iter().map(|(text, _, _)| self.measure_text(text) as i16).sum();
                 right_x -= w;
-                self.draw_text_modern(conn, right_x, ((self.height/2)+4) as i16, output, fg_color, bg_color)?;
+
+                // Draw the colored runs left to right within the module's slot
+                let mut run_x = right_x;
+                for (text, run_fg, run_bg) in &runs {
+                    self.draw_text_modern(conn, run_x, ((self.height/2)+4) as i16, text, *run_fg, *run_bg)?;
+                    run_x += self.measure_text(text) as i16;
+                }
                 right_x -= 15;
              }
         }
 
+        // 4. Draw the tabs in Tabbed, otherwise the Window Title (not even fetched while hidden)
+        self.tab_ranges.clear();
+        if !tabs.is_empty() && right_x > x_offset {
+            let tab_w = (right_x - x_offset) / tabs.len() as i16;
+            for (i, &win) in tabs.iter().enumerate() {
+                let tab_x = x_offset + i as i16 * tab_w;
+                let title = self.fit_text(&self.window_title(conn, win), (tab_w - 8).max(0) as u32);
+                if Some(win) == focused_window {
+                    conn.poly_fill_rectangle(self.window, self.gc, &[Rectangle{
+                        x: tab_x, y: 0, width: tab_w.max(0) as u16, height: self.height
+                    }])?;
+                    self.draw_text_modern(conn, tab_x + 4, ((self.height/2)+4) as i16, &title, active_fg, active_bg)?;
+                } else {
+                    self.draw_text_modern(conn, tab_x + 4, ((self.height/2)+4) as i16, &title, fg_color, bg_color)?;
+                }
+                self.tab_ranges.push((tab_x, tab_x + tab_w, win));
+            }
+        } else if let Some(win) = focused_window.filter(|_| self.show_title) {
+            let title = self.window_title(conn, win);
+            let title_w = self.measure_text(&title) as i16;
+
+            let center_x = (self.width as i16 / 2) - (title_w / 2);
+            if center_x > x_offset {
+                self.draw_text_modern(conn, center_x, ((self.height/2)+4) as i16, &title, fg_color, bg_color)?;
+            }
+        }
+
         Ok(())
     }
 
+    // Re-runs every module whose interval has elapsed and picks up the latest FIFO
+    // lines. Called from the timer tick, so in event-driven mode modules only run
+    // once at startup.
+    pub fn update_modules(&mut self) {
+        for (module, state) in self.config.modules.iter().zip(self.module_states.iter_mut()) {
+            if let Some(latest) = &state.fifo_line {
+                if let Ok(line) = latest.lock() {
+                    state.last_output = line.clone();
+                }
+            } else if state.last_update.elapsed() > Duration::from_secs(module.interval) {
+                if let Ok(output) = Command::new("sh").arg("-c").arg(&module.command).output() {
+                    state.last_output = String::from_utf8_lossy(&output.stdout).trim().to_string();
+                }
+                state.last_update = Instant::now();
+            }
+        }
+    }
+
+    // The window's UTF-8 _NET_WM_NAME, falling back to WM_NAME, or an empty string if it
+    // has neither
+    fn window_title<C: Connection>(&self, conn: &C, win: Window) -> String {
+        let sources = [(self.net_wm_name, self.utf8_string), (AtomEnum::WM_NAME.into(), AtomEnum::ANY.into())];
+        for (property, type_) in sources {
+            let reply = conn.get_property(false, win, property, type_, 0, 1024).ok().and_then(|cookie| cookie.reply().ok());
+            if let Some(prop) = reply.filter(|prop| !prop.value.is_empty()) {
+                return String::from_utf8_lossy(&prop.value).to_string();
+            }
+        }
+        String::new()
+    }
+
+    // Shortens text with a trailing ".." until it fits in max_w pixels
+    fn fit_text(&self, text: &str, max_w: u32) -> String {
+        if self.measure_text(text) <= max_w {
+            return text.to_string();
+        }
+        let mut chars: Vec<char> = text.chars().collect();
+        while !chars.is_empty() {
+            chars.pop();
+            let shortened = format!("{}..", chars.iter().collect::<String>());
+            if self.measure_text(&shortened) <= max_w {
+                return shortened;
+            }
+        }
+        String::new()
+    }
+
     // --- MODERN TEXT RENDERING ---
 
     fn measure_text(&self, text: &str) -> u32 {
@@ -242,6 +623,20 @@ impl Bar {
         text: &str, 
         text_color: u32,
         bg_color: u32
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.draw_text_on(conn, self.window, x, y, text, (text_color, bg_color))
+    }
+
+    // Renders text onto any window on the bar's screen; the bar's GC works for any
+    // drawable with the same root and depth. Colors are (text, background).
+    fn draw_text_on<C: Connection>(
+        &self,
+        conn: &C,
+        target: Window,
+        x: i16,
+        y: i16,
+        text: &str,
+        (text_color, bg_color): (u32, u32)
     ) -> Result<(), Box<dyn std::error::Error>> {
         if let Some(font) = &self.font {
             let scale = Scale::uniform(16.0); // Font Size
@@ -253,22 +648,9 @@ impl Bar {
             
             if width == 0 { return Ok(()); }
 
-            // 2. Create Pixel Buffer (ARGB or BGRA usually)
+            // 2. Create Pixel Buffer of 0xRRGGBB values, encoded for the visual once drawn
             // We initialize with the background color
-            let mut pixel_buffer = vec![0u8; width * height * 4];
-            
-            for i in 0..(width * height) {
-                // Fill with BG color
-                let b = (bg_color & 0xFF) as u8;
-                let g = ((bg_color >> 8) & 0xFF) as u8;
-                let r = ((bg_color >> 16) & 0xFF) as u8;
-                let a = 0xFF; // Full opacity
-                
-                pixel_buffer[i * 4 + 0] = b;
-                pixel_buffer[i * 4 + 1] = g;
-                pixel_buffer[i * 4 + 2] = r;
-                pixel_buffer[i * 4 + 3] = a;
-            }
+            let mut pixel_buffer = vec![bg_color; width * height];
 
             // 3. Render Glyphs
             // We render starting at (0, baseline) relative to our buffer
@@ -286,12 +668,12 @@ impl Bar {
 
                         // Check bounds (important!)
                         if px < width && py < height {
-                            let idx = (py * width + px) * 4;
+                            let idx = py * width + px;
                             
                             // Get existing color (Background)
-                            let bg_b = pixel_buffer[idx + 0] as f32;
-                            let bg_g = pixel_buffer[idx + 1] as f32;
-                            let bg_r = pixel_buffer[idx + 2] as f32;
+                            let bg_b = (pixel_buffer[idx] & 0xFF) as f32;
+                            let bg_g = ((pixel_buffer[idx] >> 8) & 0xFF) as f32;
+                            let bg_r = ((pixel_buffer[idx] >> 16) & 0xFF) as f32;
                             
                             // Get text color
                             let fg_b = (text_color & 0xFF) as f32;
@@ -299,14 +681,11 @@ impl Bar {
                             let fg_r = ((text_color >> 16) & 0xFF) as f32;
 
                             // Alpha Blend: Out = Alpha * FG + (1-Alpha) * BG
-                            let out_b = (alpha * fg_b + (1.0 - alpha) * bg_b) as u8;
-                            let out_g = (alpha * fg_g + (1.0 - alpha) * bg_g) as u8;
-                            let out_r = (alpha * fg_r + (1.0 - alpha) * bg_r) as u8;
-
-                            pixel_buffer[idx + 0] = out_b;
-                            pixel_buffer[idx + 1] = out_g;
-                            pixel_buffer[idx + 2] = out_r;
-                            // Alpha stays 0xFF
+                            let out_b = (alpha * fg_b + (1.0 - alpha) * bg_b) as u32;
+                            let out_g = (alpha * fg_g + (1.0 - alpha) * bg_g) as u32;
+                            let out_r = (alpha * fg_r + (1.0 - alpha) * bg_r) as u32;
+
+                            pixel_buffer[idx] = (out_r << 16) | (out_g << 8) | out_b;
                         }
                     });
                 }
@@ -315,15 +694,15 @@ impl Bar {
             // 4. Send Image to X Server
             conn.put_image(
                 ImageFormat::Z_PIXMAP,
-                self.window,
+                target,
                 self.gc,
                 width as u16,
                 height as u16,
                 x,
                 y - (v_metrics.ascent as i16), // Adjust Y back to top-left of rect
                 0,
-                24, // Depth (Check your screen.root_depth!)
-                &pixel_buffer
+                self.pixel_format.depth,
+                &self.pixel_format.encode(&pixel_buffer, width)
             )?;
 
         } else {
@@ -332,9 +711,187 @@ impl Bar {
         Ok(())
     }
 
+    // Creates and maps a small override-redirect window at (x, y), sized for `text`.
+    // Its text is drawn by draw_label once the window is exposed.
+    pub fn create_label<C: Connection>(
+        &self,
+        conn: &C,
+        x: i16,
+        y: i16,
+        text: &str,
+    ) -> Result<Window, Box<dyn std::error::Error>> {
+        let label = conn.generate_id()?;
+        let win_aux = CreateWindowAux::new()
+            .background_pixel(self.pixel_format.pixel(self.bg_color))
+            .override_redirect(1)
+            .event_mask(EventMask::EXPOSURE);
+
+        conn.create_window(
+            self.pixel_format.depth,
+            label,
+            self.root,
+            x,
+            y,
+            self.measure_text(text) as u16 + 8,
+            self.height,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            self.visual,
+            &win_aux,
+        )?;
+        conn.map_window(label)?;
+        Ok(label)
+    }
+
+    pub fn draw_label<C: Connection>(
+        &self,
+        conn: &C,
+        label: Window,
+        text: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.draw_text_on(conn, label, 4, ((self.height/2)+4) as i16, text, (self.fg_color, self.bg_color))
+    }
+
+    // Pixel value for a 0xRRGGBB color on this screen's visual
+    pub fn pixel(&self, rgb: u32) -> u32 {
+        self.pixel_format.pixel(rgb)
+    }
+
+    pub fn get_clicked_tab(&self, x: i16) -> Option<Window> {
+        self.tab_ranges.iter().find(|(start, end, _)| (*start..*end).contains(&x)).map(|&(_, _, win)| win)
+    }
+
+    pub fn is_over_layout(&self, x: i16) -> bool {
+        (self.layout_range.0..self.layout_range.1).contains(&x)
+    }
+
     pub fn get_clicked_workspace(&self, x: i16) -> Option<usize> {
-        if x < 0 { return None; }
-        let index = x / CELL_WIDTH;
-        if index >= 0 && index < 9 { Some(index as usize) } else { None }
+        range_at(&self.workspace_ranges, x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FG: u32 = 0xFFFFFF;
+    const BG: u32 = 0x000000;
+
+    #[test]
+    fn plain_text_is_one_run() {
+        assert_eq!(parse_markup("12:00", FG, BG), vec![("12:00".to_string(), FG, BG)]);
+        assert!(parse_markup("", FG, BG).is_empty());
+    }
+
+    #[test]
+    fn colour_tags_split_runs_and_reset() {
+        let runs = parse_markup("a%{F#ff0000}b%{B#00ff00}c%{F-}d%{B-}e", FG, BG);
+        assert_eq!(runs, vec![
+            ("a".to_string(), FG, BG),
+            ("b".to_string(), 0xff0000, BG),
+            ("c".to_string(), 0xff0000, 0x00ff00),
+            ("d".to_string(), FG, 0x00ff00),
+            ("e".to_string(), FG, BG),
+        ]);
+    }
+
+    #[test]
+    fn unknown_and_malformed_tags() {
+        // Unknown tags and bad colours are dropped without changing the colours
+        assert_eq!(parse_markup("%{U#123456}x%{F#zzz}y", FG, BG), vec![
+            ("x".to_string(), FG, BG),
+            ("y".to_string(), FG, BG),
+        ]);
+        // An unclosed tag is kept as text
+        assert_eq!(parse_markup("x%{F#ff0000", FG, BG), vec![("x%{F#ff0000".to_string(), FG, BG)]);
+    }
+
+    #[test]
+    fn cells_grow_to_fit_their_labels() {
+        // Narrow icons keep the minimum width; "code" at 60px gets padding on both sides
+        let ranges = cell_ranges(&[8, 60, 0]);
+        assert_eq!(ranges, vec![(0, CELL_WIDTH), (CELL_WIDTH, CELL_WIDTH + 60 + 2 * CELL_PADDING),
+            (CELL_WIDTH + 60 + 2 * CELL_PADDING, 2 * CELL_WIDTH + 60 + 2 * CELL_PADDING)]);
+        assert!(cell_ranges(&[]).is_empty());
+    }
+
+    #[test]
+    fn clicks_map_to_the_cell_drawn_there() {
+        let ranges = cell_ranges(&[8, 60, 8]);
+        assert_eq!(range_at(&ranges, 0), Some(0));
+        assert_eq!(range_at(&ranges, CELL_WIDTH - 1), Some(0));
+        assert_eq!(range_at(&ranges, CELL_WIDTH), Some(1));
+        // Past where a fixed-width grid would have put the third cell
+        assert_eq!(range_at(&ranges, 2 * CELL_WIDTH + 10), Some(1));
+        assert_eq!(range_at(&ranges, ranges[2].0), Some(2));
+        assert_eq!(range_at(&ranges, ranges[2].1), None);
+        assert_eq!(range_at(&ranges, -1), None);
+    }
+
+    #[test]
+    fn workspace_labels() {
+        let mut config = BarConfig {
+            workspace_style: "Icons".into(),
+            workspace_icons: vec!["web".into(), "code".into()],
+            ..BarConfig::default()
+        };
+        assert_eq!(workspace_label(&config, 1, false), "code");
+        assert_eq!(workspace_label(&config, 4, false), "5");
+        config.workspace_style = "Squares".into();
+        assert_eq!(workspace_label(&config, 0, true), "[x]");
+        assert_eq!(workspace_label(&config, 0, false), "[ ]");
+    }
+
+    fn module(command: &str, interval: u64, fifo: Option<&str>) -> BarModule {
+        BarModule { command: command.into(), interval, fifo: fifo.map(Into::into), fg: None, bg: None }
+    }
+
+    #[test]
+    fn reloaded_modules_match_on_command_and_interval() {
+        assert!(same_module(&module("date", 1, None), &module("date", 1, None)));
+        assert!(!same_module(&module("date", 1, None), &module("date", 5, None)));
+        assert!(!same_module(&module("date", 1, None), &module("uptime", 1, None)));
+        // A FIFO module is the same pipe whatever its other fields say
+        assert!(same_module(&module("", 0, Some("/tmp/a")), &module("x", 3, Some("/tmp/a"))));
+        assert!(!same_module(&module("", 0, Some("/tmp/a")), &module("", 0, Some("/tmp/b"))));
+        assert!(!same_module(&module("date", 1, None), &module("date", 1, Some("/tmp/a"))));
+    }
+
+    // Polls until `done` holds, for at most two seconds
+    fn wait_for(done: impl Fn() -> bool) -> bool {
+        let start = Instant::now();
+        while !done() {
+            if start.elapsed() > Duration::from_secs(2) {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        true
+    }
+
+    #[test]
+    fn fifo_reader_stops_when_its_module_is_dropped() {
+        let path = std::env::temp_dir().join(format!("rwm-test-fifo-{}", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let latest = Arc::new(Mutex::new(String::new()));
+        let reader = spawn_fifo_reader(path.clone(), Arc::downgrade(&latest));
+
+        assert!(wait_for(|| Path::new(&path).exists()));
+        fs::write(&path, "first\nsecond\n").unwrap();
+        assert!(wait_for(|| *latest.lock().unwrap() == "second"));
+
+        // Back to waiting for the next writer; dropping the module and waking it ends it
+        drop(latest);
+        assert!(wait_for(|| {
+            wake_fifo_reader(&path);
+            reader.is_finished()
+        }));
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn bar_sits_on_its_edge() {
+        assert_eq!(bar_y(BarPosition::Top, 1080, 20), 0);
+        assert_eq!(bar_y(BarPosition::Bottom, 1080, 20), 1060);
     }
 }
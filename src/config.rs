@@ -1,14 +1,133 @@
+use crate::layout::Layout;
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 
+const DEFAULT_FONT: &str = "6x13";
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
     #[serde(default)]
     pub bindings: HashMap<String, String>,
+    // Alternative binding sets for SwitchProfile, each replacing `bindings` entirely
+    #[serde(default)]
+    pub profiles: HashMap<String, HashMap<String, String>>,
     #[serde(default)]
     pub bar: BarConfig,
+    // How many workspaces there are
+    #[serde(default = "default_workspace_count")]
+    pub workspace_count: usize,
+    // 1-based workspace shown at startup
+    #[serde(default = "default_workspace")]
+    pub default_workspace: usize,
+    #[serde(default)]
+    pub presentation: PresentationConfig,
+    #[serde(default)]
+    pub promote_when_master: PromoteWhenMaster,
+    // How the non-master windows are arranged inside the MasterStack stack column
+    #[serde(default)]
+    pub stack_layout: StackLayout,
+    #[serde(default)]
+    pub bar_position: BarPosition,
+    // Require Quit to be pressed twice in a row
+    #[serde(default)]
+    pub confirm_quit: bool,
+    // Whether relative workspace motions wrap from the last workspace to the first
+    #[serde(default = "default_true")]
+    pub workspace_wrap: bool,
+    // Focus the window under the pointer; when off, windows are focused by clicking
+    #[serde(default = "default_true")]
+    pub focus_follows_mouse: bool,
+    // Share of each Dwindle split given to the earlier window
+    #[serde(default = "default_dwindle_ratio")]
+    pub dwindle_ratio: f32,
+    // Share of the screen width given to the MasterStack master column
+    #[serde(default = "default_master_ratio")]
+    pub master_ratio: f32,
+    // Pixels between neighbouring tiled windows
+    #[serde(default)]
+    pub gaps: u16,
+    // Leave out the gaps when a workspace has a single window
+    #[serde(default)]
+    pub smart_gaps: bool,
+    // Margin between the tiling area and the screen edges (and the bar)
+    #[serde(default)]
+    pub outer_gap: u16,
+    // Window borders; colors are "#RRGGBB"
+    #[serde(default)]
+    pub border_width: u16,
+    #[serde(default = "default_focused_border_color")]
+    pub focused_border_color: String,
+    #[serde(default = "default_unfocused_border_color")]
+    pub unfocused_border_color: String,
+    // Command run when switching to a workspace, keyed by 1-based workspace number.
+    // TOML keys are strings, so the numbers are parsed when the WindowManager starts.
+    #[serde(default)]
+    pub on_enter: HashMap<String, String>,
+    // Layout a workspace starts in and ResetLayout returns to, keyed like on_enter.
+    // Workspaces not listed use MasterStack.
+    #[serde(default)]
+    pub workspace_layouts: HashMap<String, Layout>,
+    // Command run when switching to a workspace that has no windows
+    #[serde(default)]
+    pub on_empty_workspace: Option<String>,
+    // Run instead of a Spawn command whose program isn't installed. Empty disables it.
+    #[serde(default = "default_fallback_terminal")]
+    pub fallback_terminal: String,
+    // Rounds tiled window corners by this many pixels using the Shape extension
+    #[serde(default)]
+    pub corner_radius: u16,
+    // Root window cursor, by X cursor font name (e.g. "left_ptr", "arrow", "watch")
+    #[serde(default = "default_cursor")]
+    pub cursor: String,
+    // WM_CLASS of a third-party bar. Its window is mapped unmanaged, and scrolling
+    // on it switches workspaces like rwm's own bar.
+    #[serde(default)]
+    pub external_bar_class: Option<String>,
+    // Restore each workspace's window stacking order when switching back to it
+    #[serde(default)]
+    pub preserve_stacking: bool,
+}
+
+fn default_cursor() -> String {
+    "left_ptr".to_string()
+}
+
+// Arrangement of the stack column in MasterStack
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StackLayout {
+    #[default]
+    Vertical, // One window above the other
+    Grid, // Near-square grid
+}
+
+// Screen edge the bar sits on
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BarPosition {
+    #[default]
+    Top,
+    Bottom,
+}
+
+// What PromoteMaster does when the focused window already is the master
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PromoteWhenMaster {
+    #[default]
+    SwapSecond, // Swap with the top of the stack
+    Noop,
+    Rotate, // Rotate the whole stack so the second window becomes master
+}
+
+fn default_workspace_count() -> usize {
+    9
+}
+
+fn default_workspace() -> usize {
+    1
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -19,12 +138,69 @@ pub struct BarConfig {
     pub workspace_icons: Vec<String>,
     #[serde(default)]
     pub modules: Vec<BarModule>,
+    // "#RRGGBB" colors; fall back to rwm.background/rwm.foreground X resources
+    #[serde(default)]
+    pub background: Option<String>,
+    #[serde(default)]
+    pub foreground: Option<String>,
+    // Redraw only on window manager events; no periodic clock/module updates
+    #[serde(default)]
+    pub event_driven: bool,
+    // Show the focused window's title; ToggleTitle flips this at runtime
+    #[serde(default = "default_true")]
+    pub show_title: bool,
+}
+
+// What TogglePresentation changes while presentation mode is on
+#[derive(Debug, Deserialize, Clone)]
+pub struct PresentationConfig {
+    #[serde(default = "default_true")]
+    pub hide_bar: bool,
+    #[serde(default = "default_true")]
+    pub monocle: bool,
+    // Turn focus_follows_mouse off, so a stray pointer doesn't move focus
+    #[serde(default = "default_true")]
+    pub click_to_focus: bool,
+}
+
+fn default_dwindle_ratio() -> f32 {
+    0.5
+}
+
+fn default_focused_border_color() -> String {
+    "#005577".to_string()
+}
+
+fn default_unfocused_border_color() -> String {
+    "#444444".to_string()
+}
+
+fn default_master_ratio() -> f32 {
+    0.55
+}
+
+fn default_fallback_terminal() -> String {
+    "xterm".to_string()
+}
+
+fn default_true() -> bool {
+    true
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct BarModule {
+    #[serde(default)]
     pub command: String,
+    #[serde(default)]
     pub interval: u64,
+    // Named pipe to read status lines from instead of running a command
+    #[serde(default)]
+    pub fifo: Option<String>,
+    // "#RRGGBB" colors for this module; the bar's colors are used when unset
+    #[serde(default)]
+    pub fg: Option<String>,
+    #[serde(default)]
+    pub bg: Option<String>,
 }
 
 impl Default for Config {
@@ -35,12 +211,23 @@ impl Default for Config {
         bindings.insert("Mod+p".to_string(), "Spawn dmenu_run".to_string());
         bindings.insert("Mod+Shift+q".to_string(), "KillFocused".to_string());
         bindings.insert("Mod+Control+q".to_string(), "Quit".to_string());
+        bindings.insert("Mod+Control+r".to_string(), "ReloadConfig".to_string());
+        bindings.insert("Mod+Tab".to_string(), "FocusNext".to_string());
+        bindings.insert("Mod+Shift+Tab".to_string(), "ToggleWorkspace".to_string());
         bindings.insert("Mod+j".to_string(), "FocusNext".to_string());
         bindings.insert("Mod+k".to_string(), "FocusPrev".to_string());
+        bindings.insert("Mod+Left".to_string(), "FocusLeft".to_string());
+        bindings.insert("Mod+Right".to_string(), "FocusRight".to_string());
+        bindings.insert("Mod+Up".to_string(), "FocusUp".to_string());
+        bindings.insert("Mod+Down".to_string(), "FocusDown".to_string());
+        bindings.insert("Mod+h".to_string(), "ResizeMaster -0.05".to_string());
+        bindings.insert("Mod+l".to_string(), "ResizeMaster 0.05".to_string());
         bindings.insert("Mod+Shift+j".to_string(), "MoveWindowNext".to_string());
         bindings.insert("Mod+Shift+k".to_string(), "MoveWindowPrev".to_string());
         bindings.insert("Mod+Space".to_string(), "CycleLayout".to_string());
         bindings.insert("Mod+b".to_string(), "ToggleBar".to_string());
+        bindings.insert("Mod+f".to_string(), "ToggleFullscreen".to_string());
+        bindings.insert("Mod+Shift+Space".to_string(), "ToggleFloating".to_string());
         bindings.insert("Mod+minus".to_string(), "SplitHorizontal".to_string());
         bindings.insert(
             "Mod+Shift+backslash".to_string(),
@@ -56,7 +243,43 @@ impl Default for Config {
 
         Self {
             bindings,
+            profiles: HashMap::new(),
             bar: BarConfig::default(),
+            workspace_count: default_workspace_count(),
+            default_workspace: default_workspace(),
+            presentation: PresentationConfig::default(),
+            promote_when_master: PromoteWhenMaster::default(),
+            stack_layout: StackLayout::default(),
+            bar_position: BarPosition::default(),
+            confirm_quit: false,
+            workspace_wrap: true,
+            focus_follows_mouse: true,
+            dwindle_ratio: default_dwindle_ratio(),
+            master_ratio: default_master_ratio(),
+            gaps: 0,
+            smart_gaps: false,
+            outer_gap: 0,
+            border_width: 0,
+            focused_border_color: default_focused_border_color(),
+            unfocused_border_color: default_unfocused_border_color(),
+            on_enter: HashMap::new(),
+            workspace_layouts: HashMap::new(),
+            on_empty_workspace: None,
+            fallback_terminal: default_fallback_terminal(),
+            corner_radius: 0,
+            cursor: default_cursor(),
+            external_bar_class: None,
+            preserve_stacking: false,
+        }
+    }
+}
+
+impl Default for PresentationConfig {
+    fn default() -> Self {
+        Self {
+            hide_bar: true,
+            monocle: true,
+            click_to_focus: true,
         }
     }
 }
@@ -64,7 +287,7 @@ impl Default for Config {
 impl Default for BarConfig {
     fn default() -> Self {
         Self {
-            font: "6x13".to_string(), // Fallback
+            font: DEFAULT_FONT.to_string(), // Fallback
             //font_size: 13,
             workspace_style: "Numbers".to_string(),
             workspace_icons: vec![
@@ -79,6 +302,10 @@ impl Default for BarConfig {
                 "9".to_string(),
             ],
             modules: Vec::new(),
+            background: None,
+            foreground: None,
+            event_driven: false,
+            show_title: true,
         }
     }
 }
@@ -98,7 +325,33 @@ impl Config {
                     for (key, value) in cfg.bindings {
                         config.bindings.insert(key, value);
                     }
+                    config.profiles = cfg.profiles;
                     config.bar = cfg.bar;
+                    config.workspace_count = cfg.workspace_count;
+                    config.default_workspace = cfg.default_workspace;
+                    config.presentation = cfg.presentation;
+                    config.promote_when_master = cfg.promote_when_master;
+                    config.stack_layout = cfg.stack_layout;
+                    config.bar_position = cfg.bar_position;
+                    config.confirm_quit = cfg.confirm_quit;
+                    config.workspace_wrap = cfg.workspace_wrap;
+                    config.focus_follows_mouse = cfg.focus_follows_mouse;
+                    config.dwindle_ratio = cfg.dwindle_ratio;
+                    config.master_ratio = cfg.master_ratio;
+                    config.gaps = cfg.gaps;
+                    config.smart_gaps = cfg.smart_gaps;
+                    config.outer_gap = cfg.outer_gap;
+                    config.border_width = cfg.border_width;
+                    config.focused_border_color = cfg.focused_border_color;
+                    config.unfocused_border_color = cfg.unfocused_border_color;
+                    config.on_enter = cfg.on_enter;
+                    config.workspace_layouts = cfg.workspace_layouts;
+                    config.on_empty_workspace = cfg.on_empty_workspace;
+                    config.fallback_terminal = cfg.fallback_terminal;
+                    config.corner_radius = cfg.corner_radius;
+                    config.cursor = cfg.cursor;
+                    config.external_bar_class = cfg.external_bar_class;
+                    config.preserve_stacking = cfg.preserve_stacking;
                     log::info!("Loaded config grom {:?}", config_path);
                 }
 
@@ -109,4 +362,86 @@ impl Config {
         }
         config
     }
+
+    // Fills in bar settings the TOML left unset from an X resource database
+    // (the RESOURCE_MANAGER string), e.g. "rwm.background: #282828".
+    pub fn apply_xresources(&mut self, resources: &str) {
+        for line in resources.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim().to_string();
+            match key.trim() {
+                "rwm.background" if self.bar.background.is_none() => {
+                    self.bar.background = Some(value)
+                }
+                "rwm.foreground" if self.bar.foreground.is_none() => {
+                    self.bar.foreground = Some(value)
+                }
+                "rwm.font" if self.bar.font == DEFAULT_FONT => self.bar.font = value,
+                _ => {}
+            }
+        }
+    }
+}
+
+// Parses a "#RRGGBB" color string into a 0xRRGGBB pixel value
+pub fn parse_color(color: &str) -> Option<u32> {
+    let hex = color.trim().strip_prefix('#')?;
+    // from_str_radix alone would also take a leading '+'
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn workspace_count_defaults_to_nine() {
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(config.workspace_count, 9);
+        let config: Config = toml::from_str("workspace_count = 5").unwrap();
+        assert_eq!(config.workspace_count, 5);
+    }
+
+    #[test]
+    fn focus_follows_mouse_unless_disabled() {
+        let config: Config = toml::from_str("").unwrap();
+        assert!(config.focus_follows_mouse);
+        let config: Config = toml::from_str("focus_follows_mouse = false").unwrap();
+        assert!(!config.focus_follows_mouse);
+    }
+
+    #[test]
+    fn toggle_workspace_leaves_mod_tab_to_focus_cycling() {
+        let config = Config::default();
+        assert_eq!(config.bindings["Mod+Tab"], "FocusNext");
+        assert_eq!(config.bindings["Mod+Shift+Tab"], "ToggleWorkspace");
+    }
+
+    #[test]
+    fn presentation_turns_everything_off_by_default() {
+        let config: Config = toml::from_str("[presentation]\nmonocle = false").unwrap();
+        assert!(config.presentation.hide_bar);
+        assert!(!config.presentation.monocle);
+        assert!(config.presentation.click_to_focus);
+    }
+
+    #[test]
+    fn colors_parse_from_hex() {
+        assert_eq!(parse_color("#1a2B3c"), Some(0x1a2b3c));
+        assert_eq!(parse_color("  #000000 "), Some(0));
+    }
+
+    #[test]
+    fn malformed_colors_are_rejected() {
+        for color in [
+            "", "#", "1a2b3c", "#1a2b3", "#1a2b3c4", "#gg0000", "#+12345",
+        ] {
+            assert_eq!(parse_color(color), None, "{}", color);
+        }
+    }
 }
@@ -0,0 +1,430 @@
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ClientMessageEvent, ConnectionExt, CreateWindowAux, PropMode, Screen, Window,
+    WindowClass,
+};
+use x11rb::wrapper::ConnectionExt as _;
+
+// A request a pager or taskbar sent as a client message
+pub enum Request {
+    CurrentDesktop(usize),
+    ActiveWindow(Window),
+    Fullscreen(Window, StateChange),
+    Above(Window, StateChange),
+}
+
+// Space a dock reserves along each screen edge, in pixels
+#[derive(Clone, Copy, Default)]
+pub struct Strut {
+    pub left: u32,
+    pub right: u32,
+    pub top: u32,
+    pub bottom: u32,
+}
+
+// The action of a _NET_WM_STATE request
+pub enum StateChange {
+    Remove,
+    Add,
+    Toggle,
+}
+
+// The EWMH hints rwm publishes so pagers, taskbars and toolkits know a window
+// manager is running and what it supports
+pub struct Ewmh {
+    root: Window,
+    net_supported: Atom,
+    net_supporting_wm_check: Atom,
+    net_wm_name: Atom,
+    utf8_string: Atom,
+    net_client_list: Atom,
+    net_active_window: Atom,
+    net_number_of_desktops: Atom,
+    net_current_desktop: Atom,
+    net_desktop_names: Atom,
+    net_wm_desktop: Atom,
+    net_wm_state: Atom,
+    net_wm_state_fullscreen: Atom,
+    net_wm_state_above: Atom,
+    net_wm_window_type: Atom,
+    net_wm_window_type_dock: Atom,
+    floating_types: [Atom; 4], // Dialog, utility, toolbar and splash window types
+    net_wm_strut: Atom,
+    net_wm_strut_partial: Atom,
+    clients: Vec<Window>, // Managed windows in the order they were mapped
+}
+
+impl Ewmh {
+    // Creates the _NET_SUPPORTING_WM_CHECK window and advertises rwm on the root
+    pub fn new<C: Connection>(
+        conn: &C,
+        screen: &Screen,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let intern = |name: &[u8]| -> Result<Atom, Box<dyn std::error::Error>> {
+            Ok(conn.intern_atom(false, name)?.reply()?.atom)
+        };
+        let ewmh = Self {
+            root: screen.root,
+            net_supported: intern(b"_NET_SUPPORTED")?,
+            net_supporting_wm_check: intern(b"_NET_SUPPORTING_WM_CHECK")?,
+            net_wm_name: intern(b"_NET_WM_NAME")?,
+            utf8_string: intern(b"UTF8_STRING")?,
+            net_client_list: intern(b"_NET_CLIENT_LIST")?,
+            net_active_window: intern(b"_NET_ACTIVE_WINDOW")?,
+            net_number_of_desktops: intern(b"_NET_NUMBER_OF_DESKTOPS")?,
+            net_current_desktop: intern(b"_NET_CURRENT_DESKTOP")?,
+            net_desktop_names: intern(b"_NET_DESKTOP_NAMES")?,
+            net_wm_desktop: intern(b"_NET_WM_DESKTOP")?,
+            net_wm_state: intern(b"_NET_WM_STATE")?,
+            net_wm_state_fullscreen: intern(b"_NET_WM_STATE_FULLSCREEN")?,
+            net_wm_state_above: intern(b"_NET_WM_STATE_ABOVE")?,
+            net_wm_window_type: intern(b"_NET_WM_WINDOW_TYPE")?,
+            net_wm_window_type_dock: intern(b"_NET_WM_WINDOW_TYPE_DOCK")?,
+            floating_types: [
+                intern(b"_NET_WM_WINDOW_TYPE_DIALOG")?,
+                intern(b"_NET_WM_WINDOW_TYPE_UTILITY")?,
+                intern(b"_NET_WM_WINDOW_TYPE_TOOLBAR")?,
+                intern(b"_NET_WM_WINDOW_TYPE_SPLASH")?,
+            ],
+            net_wm_strut: intern(b"_NET_WM_STRUT")?,
+            net_wm_strut_partial: intern(b"_NET_WM_STRUT_PARTIAL")?,
+            clients: Vec::new(),
+        };
+
+        // An unmapped 1x1 child of the root that names the window manager
+        let check = conn.generate_id()?;
+        conn.create_window(
+            0,
+            check,
+            screen.root,
+            -1,
+            -1,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_ONLY,
+            0,
+            &CreateWindowAux::new(),
+        )?;
+        for window in [screen.root, check] {
+            conn.change_property32(
+                PropMode::REPLACE,
+                window,
+                ewmh.net_supporting_wm_check,
+                AtomEnum::WINDOW,
+                &[check],
+            )?;
+        }
+        conn.change_property8(
+            PropMode::REPLACE,
+            check,
+            ewmh.net_wm_name,
+            ewmh.utf8_string,
+            b"rwm",
+        )?;
+
+        let supported = [
+            ewmh.net_supported,
+            ewmh.net_supporting_wm_check,
+            ewmh.net_wm_name,
+            ewmh.net_client_list,
+            ewmh.net_active_window,
+            ewmh.net_number_of_desktops,
+            ewmh.net_current_desktop,
+            ewmh.net_desktop_names,
+            ewmh.net_wm_desktop,
+            ewmh.net_wm_state,
+            ewmh.net_wm_state_fullscreen,
+            ewmh.net_wm_state_above,
+            ewmh.net_wm_window_type,
+            ewmh.net_wm_window_type_dock,
+            ewmh.net_wm_strut,
+            ewmh.net_wm_strut_partial,
+        ];
+        conn.change_property32(
+            PropMode::REPLACE,
+            ewmh.root,
+            ewmh.net_supported,
+            AtomEnum::ATOM,
+            &supported,
+        )?;
+        ewmh.publish_clients(conn)?;
+        ewmh.set_active_window(conn, None)?;
+        Ok(ewmh)
+    }
+
+    pub fn add_client<C: Connection>(
+        &mut self,
+        conn: &C,
+        window: Window,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.clients.push(window);
+        self.publish_clients(conn)
+    }
+
+    pub fn remove_client<C: Connection>(
+        &mut self,
+        conn: &C,
+        window: Window,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.clients.retain(|&w| w != window);
+        self.publish_clients(conn)
+    }
+
+    fn publish_clients<C: Connection>(&self, conn: &C) -> Result<(), Box<dyn std::error::Error>> {
+        conn.change_property32(
+            PropMode::REPLACE,
+            self.root,
+            self.net_client_list,
+            AtomEnum::WINDOW,
+            &self.clients,
+        )?;
+        Ok(())
+    }
+
+    // None when no window has focus
+    pub fn set_active_window<C: Connection>(
+        &self,
+        conn: &C,
+        window: Option<Window>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        conn.change_property32(
+            PropMode::REPLACE,
+            self.root,
+            self.net_active_window,
+            AtomEnum::WINDOW,
+            &[window.unwrap_or(x11rb::NONE)],
+        )?;
+        Ok(())
+    }
+
+    // Publishes the number of workspaces and their names
+    pub fn set_desktops<C: Connection>(
+        &self,
+        conn: &C,
+        names: &[String],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        conn.change_property32(
+            PropMode::REPLACE,
+            self.root,
+            self.net_number_of_desktops,
+            AtomEnum::CARDINAL,
+            &[names.len() as u32],
+        )?;
+        // Null-terminated UTF-8 strings, one after another
+        let mut value = Vec::new();
+        for name in names {
+            value.extend_from_slice(name.as_bytes());
+            value.push(0);
+        }
+        conn.change_property8(
+            PropMode::REPLACE,
+            self.root,
+            self.net_desktop_names,
+            self.utf8_string,
+            &value,
+        )?;
+        Ok(())
+    }
+
+    pub fn set_current_desktop<C: Connection>(
+        &self,
+        conn: &C,
+        index: usize,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        conn.change_property32(
+            PropMode::REPLACE,
+            self.root,
+            self.net_current_desktop,
+            AtomEnum::CARDINAL,
+            &[index as u32],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_window_desktop<C: Connection>(
+        &self,
+        conn: &C,
+        window: Window,
+        index: usize,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            self.net_wm_desktop,
+            AtomEnum::CARDINAL,
+            &[index as u32],
+        )?;
+        Ok(())
+    }
+
+    pub fn parse_request(&self, event: &ClientMessageEvent) -> Option<Request> {
+        let data = event.data.as_data32();
+        if event.type_ == self.net_current_desktop {
+            Some(Request::CurrentDesktop(data[0] as usize))
+        } else if event.type_ == self.net_active_window {
+            Some(Request::ActiveWindow(event.window))
+        } else if event.type_ == self.net_wm_state {
+            let change = match data[0] {
+                0 => StateChange::Remove,
+                1 => StateChange::Add,
+                _ => StateChange::Toggle,
+            };
+            if data[1..3].contains(&self.net_wm_state_fullscreen) {
+                Some(Request::Fullscreen(event.window, change))
+            } else if data[1..3].contains(&self.net_wm_state_above) {
+                Some(Request::Above(event.window, change))
+            } else {
+                None
+            }
+        } else {
+            None
+        }
+    }
+
+    // Adds or removes _NET_WM_STATE_FULLSCREEN in the window's _NET_WM_STATE, keeping
+    // any other states it has
+    pub fn set_fullscreen_state<C: Connection>(
+        &self,
+        conn: &C,
+        window: Window,
+        fullscreen: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let prop = conn
+            .get_property(false, window, self.net_wm_state, AtomEnum::ATOM, 0, 1024)?
+            .reply()?;
+        let mut states: Vec<Atom> = prop
+            .value32()
+            .map(|atoms| {
+                atoms
+                    .filter(|&a| a != self.net_wm_state_fullscreen)
+                    .collect()
+            })
+            .unwrap_or_default();
+        if fullscreen {
+            states.push(self.net_wm_state_fullscreen);
+        }
+        conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            self.net_wm_state,
+            AtomEnum::ATOM,
+            &states,
+        )?;
+        Ok(())
+    }
+
+    fn window_types<C: Connection>(
+        &self,
+        conn: &C,
+        window: Window,
+    ) -> Result<Vec<Atom>, Box<dyn std::error::Error>> {
+        let prop = conn
+            .get_property(
+                false,
+                window,
+                self.net_wm_window_type,
+                AtomEnum::ATOM,
+                0,
+                32,
+            )?
+            .reply()?;
+        Ok(prop.value32().map(Iterator::collect).unwrap_or_default())
+    }
+
+    pub fn is_dock<C: Connection>(
+        &self,
+        conn: &C,
+        window: Window,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(self
+            .window_types(conn, window)?
+            .contains(&self.net_wm_window_type_dock))
+    }
+
+    // Whether the window already asks to be fullscreen, e.g. a video player or game
+    pub fn wants_fullscreen<C: Connection>(
+        &self,
+        conn: &C,
+        window: Window,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        self.has_state(conn, window, self.net_wm_state_fullscreen)
+    }
+
+    // Whether the window already asks to be kept above the others
+    pub fn wants_above<C: Connection>(
+        &self,
+        conn: &C,
+        window: Window,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        self.has_state(conn, window, self.net_wm_state_above)
+    }
+
+    // Whether the window's _NET_WM_STATE list contains the given state atom
+    fn has_state<C: Connection>(
+        &self,
+        conn: &C,
+        window: Window,
+        state: Atom,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let prop = conn
+            .get_property(false, window, self.net_wm_state, AtomEnum::ATOM, 0, 1024)?
+            .reply()?;
+        Ok(prop
+            .value32()
+            .is_some_and(|mut atoms| atoms.any(|atom| atom == state)))
+    }
+
+    // Dialogs, utility windows, toolbars, splash screens and anything transient for
+    // another window shouldn't be tiled
+    pub fn wants_floating<C: Connection>(
+        &self,
+        conn: &C,
+        window: Window,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let types = self.window_types(conn, window)?;
+        if types.iter().any(|t| self.floating_types.contains(t)) {
+            return Ok(true);
+        }
+        let transient_for = conn
+            .get_property(
+                false,
+                window,
+                AtomEnum::WM_TRANSIENT_FOR,
+                AtomEnum::WINDOW,
+                0,
+                1,
+            )?
+            .reply()?;
+        Ok(transient_for.value_len > 0)
+    }
+
+    // Whether a change to this property can change a dock's strut
+    pub fn is_strut_property(&self, atom: Atom) -> bool {
+        atom == self.net_wm_strut || atom == self.net_wm_strut_partial
+    }
+
+    // The edges a dock reserves. _NET_WM_STRUT_PARTIAL is preferred; only its first four
+    // values (the edge widths) are used. _NET_WM_STRUT is the older form.
+    pub fn strut<C: Connection>(
+        &self,
+        conn: &C,
+        window: Window,
+    ) -> Result<Strut, Box<dyn std::error::Error>> {
+        for property in [self.net_wm_strut_partial, self.net_wm_strut] {
+            let prop = conn
+                .get_property(false, window, property, AtomEnum::CARDINAL, 0, 12)?
+                .reply()?;
+            let values: Vec<u32> = prop.value32().map(Iterator::collect).unwrap_or_default();
+            if let [left, right, top, bottom, ..] = values[..] {
+                return Ok(Strut {
+                    left,
+                    right,
+                    top,
+                    bottom,
+                });
+            }
+        }
+        Ok(Strut::default())
+    }
+}
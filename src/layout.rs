@@ -1,237 +1,618 @@
-use crate::workspace::SplitAxis;
+use crate::config::StackLayout;
+use crate::workspace::{SplitAxis, Workspace};
+use serde::Deserialize;
 use x11rb::connection::Connection;
-use x11rb::protocol::xproto::{ConfigureWindowAux, ConnectionExt, Window};
+use x11rb::protocol::shape::{self, SK, SO};
+use x11rb::protocol::xproto::{ClipOrdering, ConfigureWindowAux, ConnectionExt, Rectangle, Window};
 
-const BORDER_WIDTH: u16 = 0;
+// Bounds for a Dwindle split so neither side collapses
+pub const MIN_SPLIT_RATIO: f32 = 0.1;
+pub const MAX_SPLIT_RATIO: f32 = 0.9;
 
-#[derive(Debug, Clone, Copy)]
+// Bounds for the MasterStack master ratio so the master column can't vanish
+pub const MIN_MASTER_RATIO: f32 = 0.05;
+pub const MAX_MASTER_RATIO: f32 = 0.95;
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
 pub enum Layout {
-    VerticalStack, // Every window same height
-    MasterStack,   // One Master on left, stack on right
-    Monocle,       // Every window takes whole screen, stacked on top of each other
-    Dwindle,       // Fibonacci layout but manual selection of where next window opens
+    VerticalStack,  // Every window same height
+    MasterStack,    // One Master on left, stack on right
+    Monocle,        // Every window takes whole screen, stacked on top of each other
+    Dwindle,        // Fibonacci layout but manual selection of where next window opens
+    Grid,           // Near-square grid, filled row by row
+    Spiral,         // Fibonacci spiral, halving the remaining space each window
+    CenteredMaster, // Master in the middle, stack split between left and right columns
+    Tabbed,         // Like Monocle, with a tab per window in the bar
+}
+
+// A region of the screen. Layouts hand out one slot per window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
 }
 
-// Main entry point that dispatches to specific layout functions
+// Main entry point that dispatches to specific layout functions. Returns the
+// geometry each window was given.
 pub fn apply_layout<C: Connection>(
     conn: &C,
-    layout_kind: Layout,
-    windows: &[Window],
-    screen_width: u16,
-    screen_height: u16,
-    top_gap: u16,
-    split_history: &[SplitAxis],
-) -> Result<(), Box<dyn std::error::Error>> {
-    let usable_height = screen_height - top_gap;
-
-    match layout_kind {
-        Layout::Dwindle => tile_dwindle(
-            conn,
-            windows,
-            screen_width,
-            usable_height,
-            top_gap,
-            split_history,
-        ),
-        Layout::VerticalStack => {
-            tile_vertical_stack(conn, windows, screen_width, usable_height, top_gap)
+    workspace: &Workspace,
+    area: Rect,
+    stack_layout: StackLayout,
+    gap: u16,
+    border_width: u16,
+) -> Result<Vec<(Window, Rect)>, Box<dyn std::error::Error>> {
+    let (windows, slots) = workspace_slots(workspace, area, stack_layout, gap);
+    configure_windows(conn, &windows, &slots, border_width)
+}
+
+// The tiled windows of a workspace and the slot the workspace's layout gives each one
+fn workspace_slots(
+    workspace: &Workspace,
+    area: Rect,
+    stack_layout: StackLayout,
+    gap: u16,
+) -> (Vec<Window>, Vec<Rect>) {
+    // Floating windows keep their own geometry; their split data is skipped with them
+    let mut windows = Vec::with_capacity(workspace.windows.len());
+    let mut split_history = Vec::with_capacity(workspace.windows.len());
+    let mut split_ratios = Vec::with_capacity(workspace.windows.len());
+    for (i, &window) in workspace.windows.iter().enumerate() {
+        if workspace.floating.contains(&window) {
+            continue;
         }
-        Layout::MasterStack => {
-            tile_master_stack(conn, windows, screen_width, usable_height, top_gap)
+        windows.push(window);
+        if let (Some(&axis), Some(&ratio)) = (
+            workspace.split_history.get(i),
+            workspace.split_ratios.get(i),
+        ) {
+            split_history.push(axis);
+            split_ratios.push(ratio);
         }
-        Layout::Monocle => tile_monocle(conn, windows, screen_width, usable_height, top_gap),
     }
+    let count = windows.len();
+
+    let slots = with_gaps(area, gap, |area| match workspace.layout {
+        Layout::Dwindle => tile_dwindle(count, area, &split_history, &split_ratios),
+        Layout::VerticalStack => tile_vertical_stack(count, area),
+        Layout::MasterStack => tile_master_stack(count, area, workspace.master_ratio, stack_layout),
+        Layout::Monocle => tile_monocle(count, area),
+        Layout::Grid => tile_grid(count, area),
+        Layout::Spiral => tile_spiral(count, area),
+        Layout::CenteredMaster => tile_centered_master(count, area, workspace.master_ratio),
+        Layout::Tabbed => tile_tabbed(count, area),
+    });
+    (windows, slots)
+}
+
+// Runs a tiling function with `gap` pixels between neighbouring slots. The area is
+// grown by half a gap on each side before tiling and every slot is shrunk by the same
+// amount afterwards, so neighbours end up a full gap apart while slots on the edge
+// stay flush with `area`. Odd gaps put the extra pixel on the right/bottom.
+pub fn with_gaps(area: Rect, gap: u16, tile: impl FnOnce(Rect) -> Vec<Rect>) -> Vec<Rect> {
+    let before = (gap / 2) as i32;
+    let after = (gap - gap / 2) as i32;
+
+    let grown = Rect {
+        x: area.x - before,
+        y: area.y - before,
+        width: area.width + gap as u32,
+        height: area.height + gap as u32,
+    };
+    tile(grown)
+        .into_iter()
+        .map(|slot| Rect {
+            x: slot.x + before,
+            y: slot.y + before,
+            width: (slot.width as i32 - before - after).max(1) as u32,
+            height: (slot.height as i32 - before - after).max(1) as u32,
+        })
+        .collect()
 }
 
-pub fn tile_vertical_stack<C: Connection>(
+// Moves each window into its slot, leaving room for the border, and returns the
+// geometry each window ended up with
+pub fn configure_windows<C: Connection>(
     conn: &C,
     windows: &[Window],
-    screen_width: u16,
-    usable_height: u16,
-    top_gap: u16,
+    slots: &[Rect],
+    border_width: u16,
+) -> Result<Vec<(Window, Rect)>, Box<dyn std::error::Error>> {
+    let mut placed = Vec::with_capacity(windows.len());
+
+    for (&window, slot) in windows.iter().zip(slots) {
+        let geometry = Rect {
+            x: slot.x,
+            y: slot.y,
+            width: slot.width.saturating_sub(2 * border_width as u32).max(1),
+            height: slot.height.saturating_sub(2 * border_width as u32).max(1),
+        };
+
+        let changes = ConfigureWindowAux::new()
+            .x(geometry.x)
+            .y(geometry.y)
+            .width(geometry.width)
+            .height(geometry.height)
+            .border_width(border_width as u32);
+
+        // X errors arrive asynchronously, so a window that has already died doesn't stop
+        // the rest from being tiled; only a broken connection fails here
+        conn.configure_window(window, &changes)?;
+        placed.push((window, geometry));
+    }
+    Ok(placed)
+}
+
+// Sets a rounded-rectangle bounding shape on a window of the given size. The shape is
+// built from one-pixel rows for the corners plus a rectangle for the straight middle.
+pub fn round_corners<C: Connection>(
+    conn: &C,
+    window: Window,
+    geometry: Rect,
+    radius: u16,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let num_windows = windows.len() as u16;
+    let width = geometry.width as i32;
+    let height = geometry.height as i32;
+    // Keep the corners from overlapping on small windows
+    let r = (radius as i32).min(width / 2).min(height / 2);
 
-    if num_windows == 0 {
-        return Ok(());
+    let mut rects = Vec::with_capacity(2 * r as usize + 1);
+    for row in 0..r {
+        let dy = (r - row) as f32 - 0.5;
+        let inset = r - ((r * r) as f32 - dy * dy).max(0.0).sqrt().round() as i32;
+        let row_width = (width - 2 * inset).max(0) as u16;
+        for y in [row, height - 1 - row] {
+            rects.push(Rectangle {
+                x: inset as i16,
+                y: y as i16,
+                width: row_width,
+                height: 1,
+            });
+        }
     }
+    rects.push(Rectangle {
+        x: 0,
+        y: r as i16,
+        width: width as u16,
+        height: (height - 2 * r) as u16,
+    });
 
-    let height_per_window = usable_height / num_windows;
-    let mut y_offset = top_gap;
+    shape::rectangles(
+        conn,
+        SO::SET,
+        SK::BOUNDING,
+        ClipOrdering::UNSORTED,
+        window,
+        0,
+        0,
+        &rects,
+    )?;
+    Ok(())
+}
 
-    for (i, &window) in windows.iter().enumerate() {
-        let slot_height = if i == (num_windows - 1) as usize {
-            (usable_height + top_gap) - y_offset
+pub fn tile_vertical_stack(count: usize, area: Rect) -> Vec<Rect> {
+    let mut slots = Vec::with_capacity(count);
+    if count == 0 {
+        return slots;
+    }
+
+    let height_per_window = area.height / count as u32;
+    let mut y_offset = area.y;
+
+    for i in 0..count {
+        // The last window takes whatever the integer division left over
+        let slot_height = if i == count - 1 {
+            (area.y + area.height as i32 - y_offset) as u32
         } else {
             height_per_window
         };
 
-        let final_width = (screen_width as u32).saturating_sub((2 * BORDER_WIDTH) as u32);
-        let final_height = (slot_height as u32).saturating_sub((2 * BORDER_WIDTH) as u32);
-
-        let changes = ConfigureWindowAux::new()
-            .x(0)
-            .y(y_offset as i32)
-            .width(final_width)
-            .height(final_height)
-            .border_width(BORDER_WIDTH as u32);
+        slots.push(Rect {
+            x: area.x,
+            y: y_offset,
+            width: area.width,
+            height: slot_height,
+        });
+        y_offset += slot_height as i32;
+    }
+    slots
+}
 
-        conn.configure_window(window, &changes)?;
-        y_offset += slot_height;
+pub fn tile_master_stack(
+    count: usize,
+    area: Rect,
+    master_ratio: f32,
+    stack_layout: StackLayout,
+) -> Vec<Rect> {
+    // If only one window, it takes the full screen
+    if count <= 1 {
+        return tile_vertical_stack(count, area);
     }
-    Ok(())
+
+    let master_width = (area.width as f32 * master_ratio) as u32;
+
+    // The Master Window (Index 0)
+    let mut slots = vec![Rect {
+        width: master_width,
+        ..area
+    }];
+
+    // The Stack Windows (Indices 1..n)
+    let stack_area = Rect {
+        x: area.x + master_width as i32,
+        y: area.y,
+        width: area.width - master_width,
+        height: area.height,
+    };
+    slots.extend(match stack_layout {
+        StackLayout::Vertical => tile_vertical_stack(count - 1, stack_area),
+        StackLayout::Grid => tile_grid(count - 1, stack_area),
+    });
+    slots
 }
 
-pub fn tile_master_stack<C: Connection>(
-    conn: &C,
-    windows: &[Window],
-    screen_width: u16,
-    usable_height: u16,
-    top_gap: u16,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let num_windows = windows.len();
-    if num_windows == 0 {
-        return Ok(());
+// Master centered at `master_ratio` of the width, with the stack windows alternating
+// between a right column (odd window indices) and a left one (even indices). With a
+// single stack window there is no left column and the master moves over to the left.
+pub fn tile_centered_master(count: usize, area: Rect, master_ratio: f32) -> Vec<Rect> {
+    if count <= 1 {
+        return tile_vertical_stack(count, area);
     }
 
-    // If only one window, it takes the full screen
-    if num_windows == 1 {
-        return tile_vertical_stack(conn, windows, screen_width, usable_height, top_gap);
-    }
-
-    // Parameters
-    let master_ratio = 0.55; // Master takes 55% width
-    let master_width = (screen_width as f32 * master_ratio) as u16;
-    let stack_width = screen_width - master_width;
-
-    let master_final_w = (master_width as u32).saturating_sub((2 * BORDER_WIDTH) as u32);
-    let master_final_h = (usable_height as u32).saturating_sub((2 * BORDER_WIDTH) as u32);
-
-    // Configure the Master Window (Index 0)
-    let master_changes = ConfigureWindowAux::new()
-        .x(0)
-        .y(top_gap as i32)
-        .width(master_final_w)
-        .height(master_final_h)
-        .border_width(BORDER_WIDTH as u32);
-
-    conn.configure_window(windows[0], &master_changes)?;
-
-    // Configure the Stack Windows (Indices 1..n)
-    let stack_windows = &windows[1..];
-    let num_stack = stack_windows.len() as u16;
-    let height_per_stack = usable_height / num_stack;
-    let mut y_offset = top_gap;
-    let stack_final_w = (stack_width as u32).saturating_sub((2 * BORDER_WIDTH) as u32);
-
-    for (i, &window) in stack_windows.iter().enumerate() {
-        let slot_height = if i == (num_stack - 1) as usize {
-            (usable_height + top_gap) - y_offset
+    let master_width = (area.width as f32 * master_ratio) as u32;
+    let left_width = if count == 2 {
+        0
+    } else {
+        (area.width - master_width) / 2
+    };
+    let right_count = count / 2;
+    let left_count = (count - 1) / 2;
+
+    let left = Rect {
+        width: left_width,
+        ..area
+    };
+    let master = Rect {
+        x: area.x + left_width as i32,
+        width: master_width,
+        ..area
+    };
+    let right_x = master.x + master_width as i32;
+    let right = Rect {
+        x: right_x,
+        width: (area.x + area.width as i32 - right_x) as u32,
+        ..area
+    };
+
+    let mut right_slots = tile_vertical_stack(right_count, right).into_iter();
+    let mut left_slots = tile_vertical_stack(left_count, left).into_iter();
+    let mut slots = vec![master];
+    for i in 1..count {
+        let next = if i % 2 == 1 {
+            right_slots.next()
         } else {
-            height_per_stack
+            left_slots.next()
         };
+        slots.extend(next);
+    }
+    slots
+}
 
-        let stack_final_h = (slot_height as u32).saturating_sub((2 * BORDER_WIDTH) as u32);
+// Arranges windows in a near-square grid, filled row by row. A short last row
+// stretches its windows to fill the full width.
+pub fn tile_grid(count: usize, area: Rect) -> Vec<Rect> {
+    let mut slots = Vec::with_capacity(count);
+    if count == 0 {
+        return slots;
+    }
 
-        let changes = ConfigureWindowAux::new()
-            .x(master_width as i32)
-            .y(y_offset as i32)
-            .width(stack_final_w)
-            .height(stack_final_h)
-            .border_width(BORDER_WIDTH as u32);
+    let cols = (count as f32).sqrt().ceil() as usize;
+    let rows = count.div_ceil(cols);
+    let row_height = area.height / rows as u32;
 
-        conn.configure_window(window, &changes)?;
-        y_offset += slot_height;
+    for row in 0..rows {
+        let in_row = cols.min(count - row * cols);
+        let y = area.y + (row as u32 * row_height) as i32;
+        let height = if row == rows - 1 {
+            (area.y + area.height as i32 - y) as u32
+        } else {
+            row_height
+        };
+
+        let col_width = area.width / in_row as u32;
+        for col in 0..in_row {
+            let x = area.x + (col as u32 * col_width) as i32;
+            let width = if col == in_row - 1 {
+                (area.x + area.width as i32 - x) as u32
+            } else {
+                col_width
+            };
+            slots.push(Rect {
+                x,
+                y,
+                width,
+                height,
+            });
+        }
     }
-    Ok(())
+    slots
 }
 
-fn tile_monocle<C: Connection>(
-    conn: &C,
-    windows: &[Window],
-    screen_width: u16,
-    usable_height: u16,
-    top_gap: u16,
-) -> Result<(), Box<dyn std::error::Error>> {
+fn tile_monocle(count: usize, area: Rect) -> Vec<Rect> {
     // Every Window gets full screen dimensions
-    let changes = ConfigureWindowAux::new()
-        .x(0)
-        .y(top_gap as i32)
-        .width(screen_width as u32)
-        .height(usable_height as u32)
-        .border_width(0);
-
-    for &window in windows {
-        conn.configure_window(window, &changes)?;
-    }
-    Ok(())
+    vec![area; count]
 }
 
-pub fn tile_dwindle<C: Connection>(
-    conn: &C,
-    windows: &[Window],
-    screen_width: u16,
-    usable_height: u16,
-    top_gap: u16,
+// Every window fills the area, as in Monocle. Focusing a window raises it, so the
+// focused tab is the one on top; the tabs themselves are drawn by the bar.
+fn tile_tabbed(count: usize, area: Rect) -> Vec<Rect> {
+    vec![area; count]
+}
+
+pub fn tile_dwindle(
+    count: usize,
+    area: Rect,
     split_history: &[SplitAxis],
-) -> Result<(), Box<dyn std::error::Error>> {
-    let num_windows = windows.len();
-    if num_windows == 0 {
-        return Ok(());
-    }
-
-    let mut x = 0;
-    let mut y = top_gap as i32;
-    let mut width = screen_width as u32;
-    let mut height = usable_height as u32;
-
-    for (i, &window) in windows.iter().enumerate() {
-        if i == num_windows - 1 {
-            let final_w = width.saturating_sub((2 * BORDER_WIDTH) as u32);
-            let final_h = height.saturating_sub((2 * BORDER_WIDTH) as u32);
-            let changes = ConfigureWindowAux::new()
-                .x(x)
-                .y(y)
-                .width(final_w)
-                .height(final_h)
-                .border_width(BORDER_WIDTH as u32);
-            conn.configure_window(window, &changes)?;
+    split_ratios: &[f32],
+) -> Vec<Rect> {
+    let mut slots = Vec::with_capacity(count);
+    let mut remaining = area;
+
+    for i in 0..count {
+        if i == count - 1 {
+            slots.push(remaining);
+            break;
+        }
+
+        let axis = if i < split_history.len() {
+            split_history[i]
         } else {
-            let axis = if i < split_history.len() {
-                split_history[i]
-            } else {
-                SplitAxis::Vertical
-            };
+            SplitAxis::Vertical
+        };
 
-            let (w, h) = match axis {
-                SplitAxis::Horizontal => {
-                    let split_w = width / 2;
-                    width -= split_w;
-                    (split_w, height)
-                }
-                SplitAxis::Vertical => {
-                    let split_h = height / 2;
-                    height -= split_h;
-                    (width, split_h)
-                }
-            };
+        let ratio = split_ratios
+            .get(i)
+            .copied()
+            .unwrap_or(0.5)
+            .clamp(MIN_SPLIT_RATIO, MAX_SPLIT_RATIO);
+
+        // The window takes its share of the remaining space, the rest continues the spiral
+        match axis {
+            SplitAxis::Horizontal => {
+                let split_w = (remaining.width as f32 * ratio) as u32;
+                slots.push(Rect {
+                    width: split_w,
+                    ..remaining
+                });
+                remaining.x += split_w as i32;
+                remaining.width -= split_w;
+            }
+            SplitAxis::Vertical => {
+                let split_h = (remaining.height as f32 * ratio) as u32;
+                slots.push(Rect {
+                    height: split_h,
+                    ..remaining
+                });
+                remaining.y += split_h as i32;
+                remaining.height -= split_h;
+            }
+        }
+    }
+    slots
+}
 
-            let final_w = w.saturating_sub((2 * BORDER_WIDTH) as u32);
-            let final_h = h.saturating_sub((2 * BORDER_WIDTH) as u32);
+// Classic Fibonacci spiral. Each window takes half of what the previous one left,
+// alternating side-by-side and stacked splits, and the windows turn inward:
+// left, top, right, bottom, left...
+pub fn tile_spiral(count: usize, area: Rect) -> Vec<Rect> {
+    let mut slots = Vec::with_capacity(count);
+    let mut remaining = area;
 
-            let changes = ConfigureWindowAux::new()
-                .x(x)
-                .y(y)
-                .width(final_w)
-                .height(final_h)
-                .border_width(BORDER_WIDTH as u32);
-            conn.configure_window(window, &changes)?;
+    for i in 0..count {
+        if i == count - 1 {
+            slots.push(remaining);
+            break;
+        }
 
-            match axis {
-                SplitAxis::Horizontal => x += w as i32,
-                SplitAxis::Vertical => y += h as i32,
+        let half_w = remaining.width / 2;
+        let half_h = remaining.height / 2;
+        match i % 4 {
+            // Window on the left, the rest to its right
+            0 => {
+                slots.push(Rect {
+                    width: half_w,
+                    ..remaining
+                });
+                remaining.x += half_w as i32;
+                remaining.width -= half_w;
+            }
+            // Window on top, the rest below it
+            1 => {
+                slots.push(Rect {
+                    height: half_h,
+                    ..remaining
+                });
+                remaining.y += half_h as i32;
+                remaining.height -= half_h;
+            }
+            // Window on the right, the rest to its left
+            2 => {
+                let width = remaining.width - half_w;
+                slots.push(Rect {
+                    x: remaining.x + half_w as i32,
+                    width,
+                    ..remaining
+                });
+                remaining.width = half_w;
+            }
+            // Window at the bottom, the rest above it
+            _ => {
+                let height = remaining.height - half_h;
+                slots.push(Rect {
+                    y: remaining.y + half_h as i32,
+                    height,
+                    ..remaining
+                });
+                remaining.height = half_h;
             }
         }
     }
-    Ok(())
+    slots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AREA: Rect = Rect {
+        x: 10,
+        y: 20,
+        width: 1000,
+        height: 700,
+    };
+
+    fn overlaps(a: &Rect, b: &Rect) -> bool {
+        a.x < b.x + b.width as i32
+            && b.x < a.x + a.width as i32
+            && a.y < b.y + b.height as i32
+            && b.y < a.y + a.height as i32
+    }
+
+    // The slots cover `area` exactly: all inside it, none overlapping, no pixel left over
+    fn assert_partitions(slots: &[Rect], count: usize, area: Rect) {
+        assert_eq!(slots.len(), count);
+        for (i, a) in slots.iter().enumerate() {
+            assert!(a.x >= area.x && a.y >= area.y, "{:?} outside {:?}", a, area);
+            assert!(a.x + a.width as i32 <= area.x + area.width as i32);
+            assert!(a.y + a.height as i32 <= area.y + area.height as i32);
+            for b in &slots[i + 1..] {
+                assert!(!overlaps(a, b), "{:?} overlaps {:?}", a, b);
+            }
+        }
+        let covered: u64 = slots.iter().map(|s| s.width as u64 * s.height as u64).sum();
+        assert_eq!(covered, area.width as u64 * area.height as u64);
+    }
+
+    #[test]
+    fn tiling_layouts_partition_the_area() {
+        for count in 1..=9 {
+            let history = vec![SplitAxis::Horizontal; count];
+            let ratios = vec![0.6; count];
+            assert_partitions(&tile_vertical_stack(count, AREA), count, AREA);
+            assert_partitions(&tile_grid(count, AREA), count, AREA);
+            assert_partitions(&tile_spiral(count, AREA), count, AREA);
+            assert_partitions(&tile_centered_master(count, AREA, 0.5), count, AREA);
+            assert_partitions(&tile_dwindle(count, AREA, &history, &ratios), count, AREA);
+            for stack in [StackLayout::Vertical, StackLayout::Grid] {
+                assert_partitions(&tile_master_stack(count, AREA, 0.55, stack), count, AREA);
+            }
+        }
+    }
+
+    #[test]
+    fn no_windows_no_slots() {
+        assert!(tile_vertical_stack(0, AREA).is_empty());
+        assert!(tile_grid(0, AREA).is_empty());
+        assert!(tile_spiral(0, AREA).is_empty());
+        assert!(tile_dwindle(0, AREA, &[], &[]).is_empty());
+        assert!(tile_master_stack(0, AREA, 0.5, StackLayout::Vertical).is_empty());
+    }
+
+    #[test]
+    fn monocle_and_tabbed_give_every_window_the_area() {
+        assert_eq!(tile_monocle(3, AREA), vec![AREA; 3]);
+        assert_eq!(tile_tabbed(3, AREA), vec![AREA; 3]);
+    }
+
+    #[test]
+    fn master_stack_gives_the_master_its_ratio() {
+        let slots = tile_master_stack(3, AREA, 0.6, StackLayout::Vertical);
+        assert_eq!(slots[0], Rect { width: 600, ..AREA });
+        assert_eq!(slots[1].x, AREA.x + 600);
+        assert_eq!(slots[1].height + slots[2].height, AREA.height);
+    }
+
+    #[test]
+    fn dwindle_follows_split_axes_and_ratios() {
+        let slots = tile_dwindle(
+            3,
+            AREA,
+            &[SplitAxis::Horizontal, SplitAxis::Vertical],
+            &[0.3, 0.5],
+        );
+        assert_eq!(slots[0], Rect { width: 300, ..AREA });
+        assert_eq!(
+            slots[1],
+            Rect {
+                x: 310,
+                y: 20,
+                width: 700,
+                height: 350
+            }
+        );
+        assert_eq!(
+            slots[2],
+            Rect {
+                x: 310,
+                y: 370,
+                width: 700,
+                height: 350
+            }
+        );
+    }
+
+    #[test]
+    fn gaps_separate_neighbours_but_not_the_edges() {
+        let slots = with_gaps(AREA, 10, |area| tile_vertical_stack(2, area));
+        assert_eq!(slots[0].x, AREA.x);
+        assert_eq!(slots[0].y, AREA.y);
+        assert_eq!(slots[0].width, AREA.width);
+        assert_eq!(slots[1].y - (slots[0].y + slots[0].height as i32), 10);
+        assert_eq!(
+            slots[1].y + slots[1].height as i32,
+            AREA.y + AREA.height as i32
+        );
+        // Without a gap the tiling is untouched
+        assert_eq!(
+            with_gaps(AREA, 0, |area| tile_grid(4, area)),
+            tile_grid(4, AREA)
+        );
+    }
+
+    #[test]
+    fn floating_windows_are_left_out_with_their_split_data() {
+        let mut ws = Workspace::new(0.5, Layout::MasterStack);
+        ws.layout = Layout::Dwindle;
+        ws.windows = vec![1, 2, 3];
+        ws.split_history = vec![
+            SplitAxis::Vertical,
+            SplitAxis::Horizontal,
+            SplitAxis::Vertical,
+        ];
+        ws.split_ratios = vec![0.5, 0.4, 0.5];
+        ws.floating.push(1);
+
+        let (windows, slots) = workspace_slots(&ws, AREA, StackLayout::Vertical, 0);
+        assert_eq!(windows, [2, 3]);
+        // Window 2's horizontal split now comes first
+        assert_eq!(slots[0], Rect { width: 400, ..AREA });
+        assert_partitions(&slots, 2, AREA);
+    }
+
+    #[test]
+    fn a_smaller_area_retiles_within_it() {
+        // Showing the bar takes 24px off the top; every slot moves inside what's left
+        let with_bar = Rect {
+            y: AREA.y + 24,
+            height: AREA.height - 24,
+            ..AREA
+        };
+        let mut ws = Workspace::new(0.5, Layout::MasterStack);
+        ws.windows = vec![1, 2, 3];
+        let (_, slots) = workspace_slots(&ws, with_bar, StackLayout::Vertical, 0);
+        assert_partitions(&slots, 3, with_bar);
+    }
 }
@@ -1,5 +1,6 @@
 mod bar;
 mod config;
+mod ewmh;
 mod layout;
 mod state;
 mod workspace;
@@ -12,31 +13,69 @@ use simplelog::{
 use state::WindowManager;
 use std::collections::HashMap;
 use std::fs::File;
+use std::path::Path;
 use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 use std::time::Duration;
 use x11rb::connection::Connection;
-use x11rb::protocol::Event;
+use x11rb::errors::ReplyError;
 use x11rb::protocol::xproto::{
-    self, ClientMessageData, ClientMessageEvent, ConnectionExt, ModMask,
+    self, AtomEnum, ClientMessageData, ClientMessageEvent, ConnectionExt, ModMask,
 };
+use x11rb::protocol::{ErrorKind, Event};
 
-#[derive(Debug, Clone)]
+// Set by the SIGHUP thread, picked up by the main loop on the wake-up it sends
+static RELOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, PartialEq)]
 enum Action {
     Spawn(String),
+    SpawnIfClass(String, String),
+    ToggleCommand(String, String), // on_cmd, off_cmd
     KillFocused,
+    ClearWorkspace,
     Quit,
+    ReloadConfig,
     FocusNext,
     FocusPrev,
+    ToggleMasterFocus,
+    FocusSibling,
+    FocusLeft,
+    FocusRight,
+    FocusUp,
+    FocusDown,
     MoveWindowNext,
     MoveWindowPrev,
     CycleLayout,
+    CycleLayoutBack,
+    ResetLayout,
+    CopyLayout(usize),
     ToggleBar,
+    ToggleTitle,
+    ShowWindowIds,
+    TogglePresentation,
+    ToggleAbove,
+    ToggleFullscreen,
+    ToggleFloating,
+    ToggleOverview,
+    ToggleTiling,
     SplitVertical,
     SplitHorizontal,
+    DwindleGrow,
+    DwindleShrink,
+    ResizeMaster(f32),
     PromoteMaster,
+    MoveToMaster,
+    SwapLastTwo,
+    ReverseStack,
     Workspace(usize),
+    ToggleWorkspace,
     MoveToWorkspace(usize),
+    MoveWindowNextWs,
+    MoveWindowPrevWs,
+    SpawnAndView(usize, String),
+    SwitchProfile(String),
 }
 
 fn parse_action(cmd: &str) -> Option<Action> {
@@ -47,25 +86,73 @@ fn parse_action(cmd: &str) -> Option<Action> {
 
     match parts[0] {
         "Spawn" => Some(Action::Spawn(parts[1..].join(" "))),
+        "SpawnIfClass" => match (parts.get(1), parts.get(2..)) {
+            (Some(class), Some(cmd)) if !cmd.is_empty() => {
+                Some(Action::SpawnIfClass(class.to_string(), cmd.join(" ")))
+            }
+            _ => None,
+        },
+        "ToggleCommand" => {
+            let args = cmd.trim_start().trim_start_matches("ToggleCommand");
+            match split_quoted(args).as_slice() {
+                [on_cmd, off_cmd] => Some(Action::ToggleCommand(on_cmd.clone(), off_cmd.clone())),
+                _ => {
+                    log::warn!("ToggleCommand needs two quoted commands: {}", cmd);
+                    None
+                }
+            }
+        }
         "KillFocused" => Some(Action::KillFocused),
+        "ClearWorkspace" => Some(Action::ClearWorkspace),
         "Quit" => Some(Action::Quit),
+        "ReloadConfig" => Some(Action::ReloadConfig),
         "FocusNext" => Some(Action::FocusNext),
         "FocusPrev" => Some(Action::FocusPrev),
+        "ToggleMasterFocus" => Some(Action::ToggleMasterFocus),
+        "FocusSibling" => Some(Action::FocusSibling),
+        "FocusLeft" => Some(Action::FocusLeft),
+        "FocusRight" => Some(Action::FocusRight),
+        "FocusUp" => Some(Action::FocusUp),
+        "FocusDown" => Some(Action::FocusDown),
         "MoveWindowNext" => Some(Action::MoveWindowNext),
         "MoveWindowPrev" => Some(Action::MoveWindowPrev),
         "CycleLayout" => Some(Action::CycleLayout),
+        "CycleLayoutBack" => Some(Action::CycleLayoutBack),
+        "ResetLayout" => Some(Action::ResetLayout),
         "ToggleBar" => Some(Action::ToggleBar),
+        "ToggleTitle" => Some(Action::ToggleTitle),
+        "ShowWindowIds" => Some(Action::ShowWindowIds),
+        "TogglePresentation" => Some(Action::TogglePresentation),
+        "ToggleAbove" => Some(Action::ToggleAbove),
+        "ToggleFullscreen" => Some(Action::ToggleFullscreen),
+        "ToggleFloating" => Some(Action::ToggleFloating),
+        "ToggleOverview" => Some(Action::ToggleOverview),
+        "ToggleTiling" => Some(Action::ToggleTiling),
         "SplitHorizontal" => Some(Action::SplitHorizontal),
         "SplitVertical" => Some(Action::SplitVertical),
-        "PromoteMaster" => Some(Action::PromoteMaster),
-        "Workspace" => parts
+        "DwindleGrow" => Some(Action::DwindleGrow),
+        "DwindleShrink" => Some(Action::DwindleShrink),
+        "ResizeMaster" => parts
             .get(1)
             .and_then(|s| s.parse().ok())
-            .map(Action::Workspace),
-        "MoveToWorkspace" => parts
+            .map(Action::ResizeMaster),
+        "PromoteMaster" => Some(Action::PromoteMaster),
+        "MoveToMaster" => Some(Action::MoveToMaster),
+        "SwapLastTwo" => Some(Action::SwapLastTwo),
+        "ReverseStack" => Some(Action::ReverseStack),
+        "Workspace" => workspace_number(parts.get(1)).map(Action::Workspace),
+        "ToggleWorkspace" => Some(Action::ToggleWorkspace),
+        "MoveToWorkspace" => workspace_number(parts.get(1)).map(Action::MoveToWorkspace),
+        "MoveWindowNextWs" => Some(Action::MoveWindowNextWs),
+        "MoveWindowPrevWs" => Some(Action::MoveWindowPrevWs),
+        "CopyLayout" => workspace_number(parts.get(1)).map(Action::CopyLayout),
+        "SpawnAndView" => match (workspace_number(parts.get(1)), parts.get(2..)) {
+            (Some(i), Some(cmd)) if !cmd.is_empty() => Some(Action::SpawnAndView(i, cmd.join(" "))),
+            _ => None,
+        },
+        "SwitchProfile" => parts
             .get(1)
-            .and_then(|s| s.parse().ok())
-            .map(Action::MoveToWorkspace),
+            .map(|name| Action::SwitchProfile(name.to_string())),
         _ => {
             log::warn!("Unknown action: {}", cmd);
             None
@@ -73,6 +160,45 @@ fn parse_action(cmd: &str) -> Option<Action> {
     }
 }
 
+// A 1-based workspace number argument. 0 is rejected so the 0-based index can't underflow.
+fn workspace_number(arg: Option<&&str>) -> Option<usize> {
+    arg.and_then(|s| s.parse().ok()).filter(|&n| n > 0)
+}
+
+// Splits on whitespace, keeping text inside double or single quotes together.
+// 'ToggleCommand "picom -b" "pkill picom"' gives ["picom -b", "pkill picom"].
+fn split_quoted(s: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+    let mut in_arg = false;
+
+    for ch in s.chars() {
+        match (quote, ch) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => current.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(ch);
+                in_arg = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            (None, c) => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if in_arg {
+        args.push(current);
+    }
+    args
+}
+
 fn keysym_from_name(name: &str) -> u32 {
     match name {
         "Return" => 0xff0d,
@@ -87,6 +213,10 @@ fn keysym_from_name(name: &str) -> u32 {
         "minus" => 0x002d,
         "backslash" => 0x005c,
         "bar" => 0x007c,
+        "Left" => 0xff51,
+        "Up" => 0xff52,
+        "Right" => 0xff53,
+        "Down" => 0xff54,
         // Simple ascii mapping
         c if c.len() == 1 => {
             let ch = c.chars().next().unwrap();
@@ -100,9 +230,17 @@ fn keysym_from_name(name: &str) -> u32 {
     }
 }
 
-fn parse_keybind(bind: &str, mod_key_mask: ModMask) -> (u32, u16) {
+// The key part of a bind: a keysym looked up in the keyboard mapping, or a raw
+// keycode given as "code:N"
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BindKey {
+    Sym(u32),
+    Code(u8),
+}
+
+fn parse_keybind(bind: &str, mod_key_mask: ModMask) -> (BindKey, u16) {
     let mut mask = 0u16;
-    let mut keysym = 0u32;
+    let mut key = BindKey::Sym(0);
 
     for part in bind.split('+') {
         match part {
@@ -110,97 +248,40 @@ fn parse_keybind(bind: &str, mod_key_mask: ModMask) -> (u32, u16) {
             "Shift" => mask |= u16::from(ModMask::SHIFT),
             "Control" => mask |= u16::from(ModMask::CONTROL),
             "Alt" => mask |= u16::from(ModMask::M1),
-            key => keysym = keysym_from_name(key),
-        }
-    }
-    (keysym, mask)
-}
-
-fn detect_mod_key() -> ModMask {
-    // Simplified detection for now
-    if std::env::var("RWM_MOD").unwrap_or_default().to_lowercase() == "alt" {
-        ModMask::M1
-    } else {
-        ModMask::M4 // Super
-    }
-}
-
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    CombinedLogger::init(vec![
-        TermLogger::new(
-            LevelFilter::Info,
-            LogConfig::default(),
-            TerminalMode::Mixed,
-            ColorChoice::Auto,
-        ),
-        WriteLogger::new(
-            LevelFilter::Info,
-            LogConfig::default(),
-            File::create("/tmp/rwm.log")?,
-        ),
-    ])?;
-
-    let config = Config::load();
-
-    let (conn, screen_num) = x11rb::connect(None)?;
-    let screen = &conn.setup().roots[screen_num];
-    let root_win = screen.root;
-    let mod_mask = detect_mod_key();
-
-    log::info!(
-        "Connected. Screen: {}x{}",
-        screen.width_in_pixels,
-        screen.height_in_pixels
-    );
-
-    state::WindowManager::setup_cursor(&conn, screen)?;
-    let change = xproto::ChangeWindowAttributesAux::new().event_mask(
-        xproto::EventMask::SUBSTRUCTURE_REDIRECT | xproto::EventMask::SUBSTRUCTURE_NOTIFY,
-    );
-    conn.change_window_attributes(screen.root, &change)?;
-
-    thread::spawn(move || {
-        // Open a separate connection for the thread
-        match x11rb::connect(None) {
-            Ok((timer_conn, _)) => {
-                loop {
-                    thread::sleep(Duration::from_secs(1));
-
-                    // Create a dummy event to wake up the main loop
-                    let event = ClientMessageEvent {
-                        response_type: x11rb::protocol::xproto::CLIENT_MESSAGE_EVENT,
-                        format: 32,
-                        sequence: 0,
-                        window: root_win,
-                        type_: x11rb::protocol::xproto::AtomEnum::STRING.into(), // Using generic STRING atom
-                        data: ClientMessageData::from([0, 0, 0, 0, 0]),
-                    };
-
-                    // Send event and flush
-                    let _ = timer_conn.send_event(
-                        false,
-                        root_win,
-                        x11rb::protocol::xproto::EventMask::NO_EVENT,
-                        &event,
-                    );
-                    let _ = timer_conn.flush();
+            name => {
+                key = match name.strip_prefix("code:") {
+                    // An unparsable keycode counts as an unknown key
+                    Some(code) => code.parse().map_or(BindKey::Sym(0), BindKey::Code),
+                    None => BindKey::Sym(keysym_from_name(name)),
                 }
             }
-            Err(e) => log::error!("Timer thread failed to connect to X11: {}", e),
         }
-    });
+    }
+    (key, mask)
+}
 
+// Grabs every bind on the root window and returns the action for each (mask, keycode)
+fn grab_keys<C: Connection>(
+    conn: &C,
+    root: xproto::Window,
+    bindings: &HashMap<String, String>,
+    mod_mask: ModMask,
+) -> Result<HashMap<(u16, u8), Action>, Box<dyn std::error::Error>> {
     let mut key_actions: HashMap<(u16, u8), Action> = HashMap::new();
 
     let mut needed_keysyms = Vec::new();
     let mut raw_bindings = Vec::new();
 
-    for (key_str, action_str) in &config.bindings {
+    for (key_str, action_str) in bindings {
         if let Some(action) = parse_action(action_str) {
-            let (sym, mask) = parse_keybind(key_str, mod_mask);
-            if sym != 0 {
-                needed_keysyms.push(sym);
-                raw_bindings.push((sym, mask, action));
+            let (key, mask) = parse_keybind(key_str, mod_mask);
+            match key {
+                BindKey::Sym(0) => {}
+                BindKey::Sym(sym) => {
+                    needed_keysyms.push(sym);
+                    raw_bindings.push((key_str, key, mask, action));
+                }
+                BindKey::Code(_) => raw_bindings.push((key_str, key, mask, action)),
             }
         }
     }
@@ -229,29 +310,181 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         u16::from(ModMask::M2 | ModMask::LOCK),
     ];
 
-    for (sym, mask, action) in raw_bindings {
-        if let Some(&code) = sym_to_code.get(&sym) {
+    for (key_str, key, mask, action) in raw_bindings {
+        // Raw keycodes are grabbed as given, without a keysym lookup
+        let code = match key {
+            BindKey::Sym(sym) => sym_to_code.get(&sym).copied(),
+            BindKey::Code(code) => Some(code),
+        };
+        if let Some(code) = code {
             key_actions.insert((mask, code), action);
 
+            // Grab errors arrive asynchronously, so check each grab to report binds that
+            // another client (e.g. a hotkey daemon) already owns
+            let mut grab_error = None;
             for ignored in ignored_modifiers {
-                conn.grab_key(
-                    true,
-                    screen.root,
-                    ModMask::from(mask | ignored),
-                    code,
-                    xproto::GrabMode::ASYNC,
-                    xproto::GrabMode::ASYNC,
-                )
-                .ok();
+                let result = conn
+                    .grab_key(
+                        true,
+                        root,
+                        ModMask::from(mask | ignored),
+                        code,
+                        xproto::GrabMode::ASYNC,
+                        xproto::GrabMode::ASYNC,
+                    )?
+                    .check();
+                if let Err(e) = result {
+                    grab_error.get_or_insert(e);
+                }
+            }
+
+            match grab_error {
+                None => {}
+                Some(ReplyError::X11Error(e)) if e.error_kind == ErrorKind::Access => {
+                    log::warn!(
+                        "Could not grab {}: already grabbed by another client",
+                        key_str
+                    )
+                }
+                Some(e) => log::warn!("Could not grab {}: {}", key_str, e),
             }
         } else {
-            log::warn!("Could not find keycode for keysym: {}", sym);
+            log::warn!("Could not find keycode for {}", key_str);
+        }
+    }
+    Ok(key_actions)
+}
+
+// Finds the modifier that actually carries the Super (or, with RWM_MOD=alt, Alt) keys
+// in the server's modifier map, falling back to the usual M4 / M1
+fn detect_mod_key<C: Connection>(conn: &C) -> Result<ModMask, Box<dyn std::error::Error>> {
+    let (keysyms, fallback) =
+        if std::env::var("RWM_MOD").unwrap_or_default().to_lowercase() == "alt" {
+            ([0xffe9, 0xffea], ModMask::M1) // Alt_L, Alt_R
+        } else {
+            ([0xffeb, 0xffec], ModMask::M4) // Super_L, Super_R
+        };
+
+    let min_keycode = conn.setup().min_keycode;
+    let max_keycode = conn.setup().max_keycode;
+    let mapping = conn
+        .get_keyboard_mapping(min_keycode, max_keycode - min_keycode + 1)?
+        .reply()?;
+    let per_keycode = mapping.keysyms_per_keycode as usize;
+    let has_mod_keysym = |code: u8| {
+        code >= min_keycode
+            && mapping
+                .keysyms
+                .chunks(per_keycode)
+                .nth((code - min_keycode) as usize)
+                .is_some_and(|syms| syms.iter().any(|sym| keysyms.contains(sym)))
+    };
+
+    // The modifier map lists keycodes_per_modifier keycodes for each of
+    // Shift, Lock, Control, Mod1 .. Mod5 in turn
+    let modmap = conn.get_modifier_mapping()?.reply()?;
+    let per_modifier = modmap.keycodes_per_modifier() as usize;
+    if per_modifier > 0 {
+        for (index, codes) in modmap.keycodes.chunks(per_modifier).enumerate() {
+            if codes.iter().any(|&code| has_mod_keysym(code)) {
+                return Ok(ModMask::from(1u16 << index));
+            }
         }
     }
+
+    log::warn!(
+        "Mod key not found in the modifier map, using {:?}",
+        fallback
+    );
+    Ok(fallback)
+}
+
+// Reads rwm.toml. Bar colors and font can also come from ~/.Xresources (TOML values
+// take priority).
+fn load_config<C: Connection>(
+    conn: &C,
+    root_win: xproto::Window,
+) -> Result<Config, Box<dyn std::error::Error>> {
+    let mut config = Config::load();
+    let resources = conn
+        .get_property(
+            false,
+            root_win,
+            AtomEnum::RESOURCE_MANAGER,
+            AtomEnum::STRING,
+            0,
+            u32::MAX,
+        )?
+        .reply()?;
+    config.apply_xresources(&String::from_utf8_lossy(&resources.value));
+    Ok(config)
+}
+
+// Applies a freshly loaded config: keys are re-grabbed from [bindings] (leaving any
+// profile) and the window manager and bar pick up the rest. The mod key and the
+// bar's event_driven setting only change on restart.
+fn reload<C: Connection>(
+    conn: &C,
+    screen: &xproto::Screen,
+    config: &Config,
+    mod_mask: ModMask,
+    wm_state: &mut WindowManager,
+) -> Result<HashMap<(u16, u8), Action>, Box<dyn std::error::Error>> {
+    conn.ungrab_key(xproto::Grab::ANY, screen.root, ModMask::ANY)?;
+    let key_actions = grab_keys(conn, screen.root, &config.bindings, mod_mask)?;
+    WindowManager::setup_cursor(conn, screen, &config.cursor)?;
+    wm_state.reload_config(conn, config.clone())?;
+    log::info!("Config reloaded with {} keybinds", key_actions.len());
+    Ok(key_actions)
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    CombinedLogger::init(vec![
+        TermLogger::new(
+            LevelFilter::Info,
+            LogConfig::default(),
+            TerminalMode::Mixed,
+            ColorChoice::Auto,
+        ),
+        WriteLogger::new(
+            LevelFilter::Info,
+            LogConfig::default(),
+            File::create("/tmp/rwm.log")?,
+        ),
+    ])?;
+
+    let (conn, screen_num) = x11rb::connect(None)?;
+    let screen = &conn.setup().roots[screen_num];
+    let root_win = screen.root;
+    let mod_mask = detect_mod_key(&conn)?;
+
+    log::info!(
+        "Connected. Screen: {}x{}",
+        screen.width_in_pixels,
+        screen.height_in_pixels
+    );
+
+    let mut config = load_config(&conn, root_win)?;
+
+    state::WindowManager::setup_cursor(&conn, screen, &config.cursor)?;
+    let change = xproto::ChangeWindowAttributesAux::new().event_mask(
+        xproto::EventMask::SUBSTRUCTURE_REDIRECT | xproto::EventMask::SUBSTRUCTURE_NOTIFY,
+    );
+    conn.change_window_attributes(screen.root, &change)?;
+
+    // In event-driven mode the bar is only redrawn by window manager events, so there
+    // is no need for the once-per-second wake-up
+    let timer_running = !config.bar.event_driven;
+    if timer_running {
+        spawn_timer_thread(root_win);
+    }
+    spawn_reload_thread(root_win);
+
+    let mut key_actions = grab_keys(&conn, root_win, &config.bindings, mod_mask)?;
     conn.flush()?;
     log::info!("RWM STARTED with {} keybinds", key_actions.len());
 
-    let mut wm_state = WindowManager::new(&conn, screen, config.clone())?;
+    let mut wm_state = WindowManager::new(&conn, screen, config.clone(), mod_mask)?;
 
     loop {
         conn.flush()?;
@@ -264,14 +497,53 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 let clean_mask =
                     u16::from(mask) & !(u16::from(ModMask::M2) | u16::from(ModMask::LOCK));
 
-                if let Some(action) = key_actions.get(&(clean_mask, evt.detail)) {
+                // Cloned so SwitchProfile can replace the bindings it came from
+                if let Some(action) = key_actions.get(&(clean_mask, evt.detail)).cloned() {
                     log::info!("Executing: {:?}", action);
-                    match action {
-                        Action::Spawn(cmd) => spawn(cmd),
+                    // Any other action cancels a Quit waiting for confirmation
+                    if !matches!(action, Action::Quit) {
+                        wm_state.cancel_quit(&conn)?;
+                    }
+                    // Window id labels go away on the next key, except the one toggling them
+                    if !matches!(action, Action::ShowWindowIds) {
+                        wm_state.clear_window_ids(&conn)?;
+                    }
+                    match &action {
+                        Action::Spawn(cmd) => {
+                            // A "a || b" chain handles its own fallback in the shell
+                            if cmd.contains("||")
+                                || program_exists(cmd)
+                                || config.fallback_terminal.is_empty()
+                            {
+                                spawn(cmd);
+                            } else {
+                                log::warn!(
+                                    "'{}' is not installed, running '{}' instead",
+                                    cmd,
+                                    config.fallback_terminal
+                                );
+                                spawn(&config.fallback_terminal);
+                            }
+                        }
+                        Action::SpawnIfClass(class, cmd) => {
+                            if wm_state.focused_has_class(&conn, class)? {
+                                spawn(cmd);
+                            }
+                        }
+                        Action::ToggleCommand(on_cmd, off_cmd) => {
+                            wm_state.toggle_command(on_cmd, off_cmd)
+                        }
+                        Action::ReloadConfig => {
+                            config = load_config(&conn, root_win)?;
+                            key_actions = reload(&conn, screen, &config, mod_mask, &mut wm_state)?;
+                        }
                         Action::KillFocused => wm_state.kill_focused_window(&conn)?,
+                        Action::ClearWorkspace => wm_state.clear_workspace(&conn)?,
                         Action::Quit => {
-                            wm_state.kill_all_windows(&conn)?;
-                            break;
+                            if wm_state.confirm_quit(&conn)? {
+                                wm_state.kill_all_windows(&conn)?;
+                                break;
+                            }
                         }
                         Action::FocusNext => {
                             wm_state.cycle_focus(&conn, state::FocusDirection::Next)?
@@ -279,6 +551,18 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         Action::FocusPrev => {
                             wm_state.cycle_focus(&conn, state::FocusDirection::Prev)?
                         }
+                        Action::ToggleMasterFocus => wm_state.toggle_master_focus(&conn)?,
+                        Action::FocusSibling => wm_state.focus_sibling(&conn)?,
+                        Action::FocusLeft => {
+                            wm_state.focus_direction(&conn, state::Direction::Left)?
+                        }
+                        Action::FocusRight => {
+                            wm_state.focus_direction(&conn, state::Direction::Right)?
+                        }
+                        Action::FocusUp => wm_state.focus_direction(&conn, state::Direction::Up)?,
+                        Action::FocusDown => {
+                            wm_state.focus_direction(&conn, state::Direction::Down)?
+                        }
                         Action::MoveWindowNext => {
                             wm_state.move_focused_window(&conn, state::FocusDirection::Next)?
                         }
@@ -286,42 +570,415 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                             wm_state.move_focused_window(&conn, state::FocusDirection::Prev)?
                         }
                         Action::CycleLayout => wm_state.cycle_layout(&conn)?,
+                        Action::CycleLayoutBack => wm_state.cycle_layout_back(&conn)?,
+                        Action::ResetLayout => wm_state.reset_layout(&conn)?,
+                        Action::CopyLayout(i) => {
+                            if let Some(idx) = i.checked_sub(1) {
+                                wm_state.copy_layout_from(&conn, idx)?
+                            }
+                        }
                         Action::ToggleBar => wm_state.toggle_bar(&conn)?,
+                        Action::ToggleTitle => wm_state.toggle_title(&conn)?,
+                        Action::ShowWindowIds => {
+                            wm_state.show_window_ids(&conn)?;
+                            // Without the timer thread nothing else would clear them
+                            if !timer_running {
+                                spawn_wakeup_after(root_win, state::WINDOW_ID_TIMEOUT);
+                            }
+                        }
+                        Action::TogglePresentation => wm_state.toggle_presentation(&conn)?,
+                        Action::ToggleAbove => wm_state.toggle_above(&conn)?,
+                        Action::ToggleFullscreen => wm_state.toggle_fullscreen(&conn)?,
+                        Action::ToggleFloating => wm_state.toggle_floating(&conn)?,
+                        Action::ToggleOverview => wm_state.toggle_overview(&conn)?,
+                        Action::ToggleTiling => wm_state.toggle_tiling(&conn)?,
                         Action::SplitHorizontal => {
                             wm_state.set_split_direction(&conn, workspace::SplitAxis::Horizontal)?
                         }
                         Action::SplitVertical => {
                             wm_state.set_split_direction(&conn, workspace::SplitAxis::Vertical)?
                         }
+                        Action::DwindleGrow => wm_state.adjust_dwindle_ratio(&conn, 0.05)?,
+                        Action::DwindleShrink => wm_state.adjust_dwindle_ratio(&conn, -0.05)?,
+                        Action::ResizeMaster(delta) => {
+                            wm_state.adjust_master_ratio(&conn, *delta)?
+                        }
                         Action::PromoteMaster => wm_state.promote_focused_to_master(&conn)?,
-                        Action::Workspace(i) => wm_state.switch_workspace(&conn, i - 1)?, // Config is 1-based, internal is 0-based
+                        Action::MoveToMaster => wm_state.move_focused_to_master(&conn)?,
+                        Action::SwapLastTwo => wm_state.swap_last_two(&conn)?,
+                        Action::ReverseStack => wm_state.reverse_stack(&conn)?,
+                        // Config is 1-based, internal is 0-based
+                        Action::Workspace(i) => {
+                            if let Some(idx) = i.checked_sub(1) {
+                                wm_state.switch_workspace(&conn, idx)?
+                            }
+                        }
+                        Action::ToggleWorkspace => wm_state.toggle_workspace(&conn)?,
                         Action::MoveToWorkspace(i) => {
-                            wm_state.move_window_to_workspace(&conn, i - 1)?
+                            if let Some(idx) = i.checked_sub(1) {
+                                wm_state.move_window_to_workspace(&conn, idx)?
+                            }
+                        }
+                        Action::MoveWindowNextWs => wm_state.move_window_to_adjacent_workspace(
+                            &conn,
+                            state::FocusDirection::Next,
+                        )?,
+                        Action::MoveWindowPrevWs => wm_state.move_window_to_adjacent_workspace(
+                            &conn,
+                            state::FocusDirection::Prev,
+                        )?,
+                        Action::SpawnAndView(i, cmd) => {
+                            if let Some(idx) = i.checked_sub(1) {
+                                wm_state.set_spawn_target(idx);
+                                spawn(cmd);
+                                wm_state.switch_workspace(&conn, idx)?;
+                            }
+                        }
+                        Action::SwitchProfile(name) => {
+                            // "default" is the top-level bindings unless a profile overrides it
+                            let bindings = config
+                                .profiles
+                                .get(name)
+                                .or_else(|| (name == "default").then_some(&config.bindings));
+                            match bindings {
+                                Some(bindings) => {
+                                    conn.ungrab_key(xproto::Grab::ANY, root_win, ModMask::ANY)?;
+                                    key_actions = grab_keys(&conn, root_win, bindings, mod_mask)?;
+                                    log::info!(
+                                        "Switched to profile '{}' with {} keybinds",
+                                        name,
+                                        key_actions.len()
+                                    );
+                                }
+                                None => log::warn!("Unknown profile: {}", name),
+                            }
                         }
                     }
                 }
             }
             Event::MapRequest(evt) => wm_state.handle_map_request(&conn, evt.window)?,
+            Event::UnmapNotify(evt) => wm_state.handle_unmap_notify(&conn, evt)?,
             Event::DestroyNotify(evt) => wm_state.handle_destroy_notify(&conn, evt.window)?,
+            Event::ConfigureRequest(evt) => wm_state.handle_configure_request(&conn, evt)?,
+            Event::ConfigureNotify(evt) => wm_state.handle_configure_notify(&conn, evt)?,
             Event::Expose(evt) => wm_state.handle_expose(&conn, evt)?,
+            Event::PropertyNotify(evt) => wm_state.handle_property_notify(&conn, evt)?,
             Event::EnterNotify(evt) => wm_state.handle_enter_notify(&conn, evt)?,
-            Event::ButtonPress(evt) => {
-                if evt.event == wm_state.bar.window {
-                    wm_state.handle_bar_click(&conn, evt.event_x)?;
-                }
+            Event::ButtonPress(evt) if evt.event == wm_state.bar.window => {
+                wm_state.handle_bar_click(&conn, evt.event_x, evt.detail)?;
             }
-            Event::ClientMessage(_) => {
+            Event::ButtonPress(evt) if Some(evt.event) == wm_state.external_bar => {
+                wm_state.handle_external_bar_scroll(&conn, evt.detail)?;
+            }
+            // Mod+Button on a managed window
+            Event::ButtonPress(evt) => wm_state.start_drag(&conn, evt)?,
+            Event::MotionNotify(evt) => wm_state.handle_motion(&conn, evt)?,
+            Event::ButtonRelease(_) => wm_state.end_drag(),
+            // Wake-ups from the timer and reload threads use the STRING type
+            Event::ClientMessage(evt) if evt.type_ == u32::from(AtomEnum::STRING) => {
+                if RELOAD_REQUESTED.swap(false, Ordering::SeqCst) {
+                    config = load_config(&conn, root_win)?;
+                    key_actions = reload(&conn, screen, &config, mod_mask, &mut wm_state)?;
+                }
                 wm_state.handle_timer_tick(&conn)?;
             }
+            Event::ClientMessage(evt) => wm_state.handle_client_message(&conn, evt)?,
+            // Requests on a window that died mid-layout fail here, after the rest of the
+            // layout went through. Its DestroyNotify cleans it up.
+            Event::Error(e) if e.error_kind == ErrorKind::Window => {
+                log::debug!("Ignoring request on a destroyed window: {:?}", e);
+            }
+            Event::Error(e) => log::warn!("X11 error: {:?}", e),
             _ => {}
         }
     }
     Ok(())
 }
 
+// Turns SIGHUP into a config reload. The main loop is blocked waiting for X events,
+// so the thread also sends it one to wake it up.
+fn spawn_reload_thread(root_win: xproto::Window) {
+    let mut signals = match signal_hook::iterator::Signals::new([signal_hook::consts::SIGHUP]) {
+        Ok(signals) => signals,
+        Err(e) => {
+            log::error!("Could not install SIGHUP handler: {}", e);
+            return;
+        }
+    };
+    thread::spawn(move || match x11rb::connect(None) {
+        Ok((reload_conn, _)) => {
+            for _ in signals.forever() {
+                log::info!("SIGHUP received, reloading config");
+                RELOAD_REQUESTED.store(true, Ordering::SeqCst);
+                let _ = send_wakeup(&reload_conn, root_win);
+            }
+        }
+        Err(e) => log::error!("Reload thread failed to connect to X11: {}", e),
+    });
+}
+
+// Sends a dummy ClientMessage to the root window to wake up the main loop
+fn send_wakeup<C: Connection>(
+    conn: &C,
+    root_win: xproto::Window,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let event = ClientMessageEvent {
+        response_type: x11rb::protocol::xproto::CLIENT_MESSAGE_EVENT,
+        format: 32,
+        sequence: 0,
+        window: root_win,
+        type_: x11rb::protocol::xproto::AtomEnum::STRING.into(), // Using generic STRING atom
+        data: ClientMessageData::from([0, 0, 0, 0, 0]),
+    };
+    conn.send_event(
+        false,
+        root_win,
+        x11rb::protocol::xproto::EventMask::NO_EVENT,
+        event,
+    )?;
+    conn.flush()?;
+    Ok(())
+}
+
+fn spawn_timer_thread(root_win: xproto::Window) {
+    thread::spawn(move || {
+        // Open a separate connection for the thread
+        match x11rb::connect(None) {
+            Ok((timer_conn, _)) => loop {
+                thread::sleep(Duration::from_secs(1));
+                let _ = send_wakeup(&timer_conn, root_win);
+            },
+            Err(e) => log::error!("Timer thread failed to connect to X11: {}", e),
+        }
+    });
+}
+
+// Wakes the main loop once after `delay`, for timeouts in event-driven mode
+fn spawn_wakeup_after(root_win: xproto::Window, delay: Duration) {
+    thread::spawn(move || {
+        thread::sleep(delay);
+        match x11rb::connect(None) {
+            Ok((wakeup_conn, _)) => {
+                let _ = send_wakeup(&wakeup_conn, root_win);
+            }
+            Err(e) => log::error!("Wake-up thread failed to connect to X11: {}", e),
+        }
+    });
+}
+
+// Whether the program a shell command starts can be found. Commands run through
+// `sh -c`, so a missing program never fails Command::spawn itself. Commands that
+// begin with a variable assignment are assumed to be fine.
+fn program_exists(command: &str) -> bool {
+    let Some(program) = command.split_whitespace().next() else {
+        return false;
+    };
+    if program.contains('=') {
+        return true;
+    }
+    if program.contains('/') {
+        return Path::new(program).exists();
+    }
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(program).exists()))
+}
+
 fn spawn(command: &str) {
     match Command::new("sh").arg("-c").arg(command).spawn() {
         Ok(_) => log::info!("Spawned {}", command),
         Err(e) => log::error!("Failed to spawn {}: {}", command, e),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The config text for an action. The match is exhaustive, so a new Action variant
+    // doesn't compile until it is covered here.
+    fn action_text(action: &Action) -> String {
+        match action {
+            Action::Spawn(cmd) => format!("Spawn {}", cmd),
+            Action::SpawnIfClass(class, cmd) => format!("SpawnIfClass {} {}", class, cmd),
+            Action::ToggleCommand(on, off) => format!("ToggleCommand \"{}\" \"{}\"", on, off),
+            Action::KillFocused => "KillFocused".into(),
+            Action::ClearWorkspace => "ClearWorkspace".into(),
+            Action::Quit => "Quit".into(),
+            Action::ReloadConfig => "ReloadConfig".into(),
+            Action::FocusNext => "FocusNext".into(),
+            Action::FocusPrev => "FocusPrev".into(),
+            Action::ToggleMasterFocus => "ToggleMasterFocus".into(),
+            Action::FocusSibling => "FocusSibling".into(),
+            Action::FocusLeft => "FocusLeft".into(),
+            Action::FocusRight => "FocusRight".into(),
+            Action::FocusUp => "FocusUp".into(),
+            Action::FocusDown => "FocusDown".into(),
+            Action::MoveWindowNext => "MoveWindowNext".into(),
+            Action::MoveWindowPrev => "MoveWindowPrev".into(),
+            Action::CycleLayout => "CycleLayout".into(),
+            Action::CycleLayoutBack => "CycleLayoutBack".into(),
+            Action::ResetLayout => "ResetLayout".into(),
+            Action::CopyLayout(i) => format!("CopyLayout {}", i),
+            Action::ToggleBar => "ToggleBar".into(),
+            Action::ToggleTitle => "ToggleTitle".into(),
+            Action::ShowWindowIds => "ShowWindowIds".into(),
+            Action::TogglePresentation => "TogglePresentation".into(),
+            Action::ToggleAbove => "ToggleAbove".into(),
+            Action::ToggleFullscreen => "ToggleFullscreen".into(),
+            Action::ToggleFloating => "ToggleFloating".into(),
+            Action::ToggleOverview => "ToggleOverview".into(),
+            Action::ToggleTiling => "ToggleTiling".into(),
+            Action::SplitVertical => "SplitVertical".into(),
+            Action::SplitHorizontal => "SplitHorizontal".into(),
+            Action::DwindleGrow => "DwindleGrow".into(),
+            Action::DwindleShrink => "DwindleShrink".into(),
+            Action::ResizeMaster(delta) => format!("ResizeMaster {}", delta),
+            Action::PromoteMaster => "PromoteMaster".into(),
+            Action::MoveToMaster => "MoveToMaster".into(),
+            Action::SwapLastTwo => "SwapLastTwo".into(),
+            Action::ReverseStack => "ReverseStack".into(),
+            Action::Workspace(i) => format!("Workspace {}", i),
+            Action::ToggleWorkspace => "ToggleWorkspace".into(),
+            Action::MoveToWorkspace(i) => format!("MoveToWorkspace {}", i),
+            Action::MoveWindowNextWs => "MoveWindowNextWs".into(),
+            Action::MoveWindowPrevWs => "MoveWindowPrevWs".into(),
+            Action::SpawnAndView(i, cmd) => format!("SpawnAndView {} {}", i, cmd),
+            Action::SwitchProfile(name) => format!("SwitchProfile {}", name),
+        }
+    }
+
+    #[test]
+    fn every_action_round_trips() {
+        let actions = [
+            Action::Spawn("kitty --single-instance".into()),
+            Action::SpawnIfClass("firefox".into(), "firefox --new-window".into()),
+            Action::ToggleCommand("picom -b".into(), "pkill picom".into()),
+            Action::KillFocused,
+            Action::ClearWorkspace,
+            Action::Quit,
+            Action::ReloadConfig,
+            Action::FocusNext,
+            Action::FocusPrev,
+            Action::ToggleMasterFocus,
+            Action::FocusSibling,
+            Action::FocusLeft,
+            Action::FocusRight,
+            Action::FocusUp,
+            Action::FocusDown,
+            Action::MoveWindowNext,
+            Action::MoveWindowPrev,
+            Action::CycleLayout,
+            Action::CycleLayoutBack,
+            Action::ResetLayout,
+            Action::CopyLayout(3),
+            Action::ToggleBar,
+            Action::ToggleTitle,
+            Action::ShowWindowIds,
+            Action::TogglePresentation,
+            Action::ToggleAbove,
+            Action::ToggleFullscreen,
+            Action::ToggleFloating,
+            Action::ToggleOverview,
+            Action::ToggleTiling,
+            Action::SplitVertical,
+            Action::SplitHorizontal,
+            Action::DwindleGrow,
+            Action::DwindleShrink,
+            Action::ResizeMaster(-0.05),
+            Action::PromoteMaster,
+            Action::MoveToMaster,
+            Action::SwapLastTwo,
+            Action::ReverseStack,
+            Action::Workspace(9),
+            Action::ToggleWorkspace,
+            Action::MoveToWorkspace(2),
+            Action::MoveWindowNextWs,
+            Action::MoveWindowPrevWs,
+            Action::SpawnAndView(4, "thunar".into()),
+            Action::SwitchProfile("gaming".into()),
+        ];
+        for action in actions {
+            let text = action_text(&action);
+            assert_eq!(parse_action(&text), Some(action), "{}", text);
+        }
+    }
+
+    #[test]
+    fn spawn_keeps_quoted_arguments_for_the_shell() {
+        let action = parse_action(r#"Spawn notify-send "hello world" 'a b'"#);
+        let Some(Action::Spawn(cmd)) = action else {
+            panic!("not a Spawn: {:?}", action);
+        };
+        assert_eq!(cmd, r#"notify-send "hello world" 'a b'"#);
+        assert_eq!(split_quoted(&cmd), ["notify-send", "hello world", "a b"]);
+    }
+
+    #[test]
+    fn malformed_actions_are_rejected() {
+        for cmd in [
+            "",
+            "NoSuchAction",
+            "Workspace",
+            "Workspace two",
+            "ResizeMaster big",
+            "SpawnIfClass firefox",
+            "SpawnAndView 2",
+            "SpawnAndView 0 thunar",
+            "Workspace 0",
+            "CopyLayout 0",
+            "MoveToWorkspace 0",
+            "ToggleCommand \"picom -b\"",
+            "SwitchProfile",
+        ] {
+            assert_eq!(parse_action(cmd), None, "{}", cmd);
+        }
+    }
+
+    #[test]
+    fn keysyms_for_named_and_ascii_keys() {
+        assert_eq!(keysym_from_name("Return"), 0xff0d);
+        assert_eq!(keysym_from_name("Space"), 0x0020);
+        assert_eq!(keysym_from_name("Tab"), 0xff09);
+        assert_eq!(keysym_from_name("Escape"), 0xff1b);
+        assert_eq!(keysym_from_name("minus"), 0x002d);
+        assert_eq!(keysym_from_name("Left"), 0xff51);
+        assert_eq!(keysym_from_name("Down"), 0xff54);
+        assert_eq!(keysym_from_name("q"), u32::from(b'q'));
+        assert_eq!(keysym_from_name("7"), u32::from(b'7'));
+        assert_eq!(keysym_from_name("/"), u32::from(b'/'));
+        assert_eq!(keysym_from_name("NoSuchKey"), 0);
+        assert_eq!(keysym_from_name(" "), 0);
+    }
+
+    #[test]
+    fn keybinds_parse_to_key_and_mask() {
+        let mod4 = ModMask::M4;
+        assert_eq!(
+            parse_keybind("Mod+Shift+q", mod4),
+            (
+                BindKey::Sym(u32::from(b'q')),
+                u16::from(ModMask::M4) | u16::from(ModMask::SHIFT)
+            )
+        );
+        assert_eq!(
+            parse_keybind("Control+Alt+Return", mod4),
+            (
+                BindKey::Sym(0xff0d),
+                u16::from(ModMask::CONTROL) | u16::from(ModMask::M1)
+            )
+        );
+        assert_eq!(
+            parse_keybind("Mod+code:94", mod4),
+            (BindKey::Code(94), u16::from(ModMask::M4))
+        );
+    }
+
+    #[test]
+    fn garbage_keybinds_have_no_key() {
+        let mod4 = ModMask::M4;
+        assert_eq!(parse_keybind("Mod+code:abc", mod4).0, BindKey::Sym(0));
+        assert_eq!(parse_keybind("Mod+code:300", mod4).0, BindKey::Sym(0));
+        assert_eq!(parse_keybind("Mod+NoSuchKey", mod4).0, BindKey::Sym(0));
+        assert_eq!(parse_keybind("", mod4), (BindKey::Sym(0), 0));
+    }
+}
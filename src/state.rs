@@ -1,29 +1,134 @@
 use crate::bar::Bar;
-use crate::config::Config;
-use crate::layout::{self, Layout};
-use crate::workspace::{SplitAxis, Workspace};
+use crate::config::{
+    self, BarPosition, Config, PresentationConfig, PromoteWhenMaster, StackLayout,
+};
+use crate::ewmh::{self, Ewmh};
+use crate::layout::{self, Layout, Rect};
+use crate::workspace::{SplitAxis, WindowEntry, Workspace};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
 use x11rb::connection::Connection;
+use x11rb::protocol::randr::{self, ConnectionExt as _};
 use x11rb::protocol::xproto::{
-    self, ChangeWindowAttributesAux, ConfigureWindowAux, ConnectionExt, EnterNotifyEvent,
-    EventMask, ExposeEvent, InputFocus, NotifyDetail, NotifyMode, Screen, StackMode, Window,
+    self, Atom, AtomEnum, ButtonIndex, ButtonPressEvent, ChangeWindowAttributesAux,
+    ClientMessageEvent, ConfigureNotifyEvent, ConfigureRequestEvent, ConfigureWindowAux,
+    ConnectionExt, EnterNotifyEvent, EventMask, ExposeEvent, InputFocus, MapState, ModMask,
+    MotionNotifyEvent, NotifyDetail, NotifyMode, PropertyNotifyEvent, Screen, StackMode,
+    UnmapNotifyEvent, Window,
 };
 
+// Minimum time between snapping the same window back into its tile
+const SNAP_DEBOUNCE: Duration = Duration::from_millis(250);
+
+// Glyph index of the default root cursor in the X cursor font
+const LEFT_PTR_GLYPH: u16 = 68;
+
+// How long a SpawnAndView target waits for its window before it stops capturing new windows
+const SPAWN_TARGET_TIMEOUT: Duration = Duration::from_secs(10);
+// Window in which a second Quit press confirms the first when confirm_quit is on
+const QUIT_CONFIRM_TIMEOUT: Duration = Duration::from_secs(2);
+// How long ShowWindowIds labels stay up, unless a key clears them first
+pub const WINDOW_ID_TIMEOUT: Duration = Duration::from_secs(3);
+
 pub enum FocusDirection {
     Next,
     Prev,
 }
 
+// A direction on screen, for moving focus by window position rather than stack order
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+#[derive(Clone, Copy)]
+enum DragKind {
+    Move,   // Mod+Button1
+    Resize, // Mod+Button3, from the bottom-right corner
+}
+
+// A mouse drag on a floating window. Motion is applied relative to where it started,
+// so the window doesn't jump to the pointer.
+struct Drag {
+    window: Window,
+    kind: DragKind,
+    start_pointer: (i16, i16),
+    start_geometry: Rect,
+}
+
+// Settings captured when presentation mode starts, restored when it ends
+struct PresentationSnapshot {
+    workspace: usize,
+    layout: Layout,
+    bar_visible: bool,
+    focus_follows_mouse: bool,
+}
+
 pub struct WindowManager {
     workspaces: Vec<Workspace>,
     active_workspace_idx: usize,
+    previous_workspace_idx: Option<usize>, // Workspace left by the last switch_workspace
     focused_window: Option<Window>,
+    previous_focus: Option<Window>, // Window focused before focused_window
     pub bar: Bar,
+    ewmh: Ewmh,
     screen_width: u16,
     screen_height: u16,
+    // Geometry of each monitor, left to right, and the workspace it shows. The active
+    // workspace is always the one on active_monitor.
+    monitors: Vec<Rect>,
+    monitor_workspace: Vec<usize>,
+    active_monitor: usize,
     root: Window,
-    current_top_gap: u16,
+    bar_gap: u16, // Space kept clear for the bar, 0 while it's hidden
+    bar_position: BarPosition,
     pending_split: SplitAxis,
     last_mouse_pos: Option<(i16, i16)>,
+    spawn_target: Option<(usize, Instant)>,
+    presentation: PresentationConfig,
+    presentation_snapshot: Option<PresentationSnapshot>,
+    promote_when_master: PromoteWhenMaster,
+    stack_layout: StackLayout,
+    external_bar_class: Option<String>,
+    pub external_bar: Option<Window>, // Third-party bar window whose scroll events we grabbed
+    docks: HashMap<Window, ewmh::Strut>, // Unmanaged dock windows and the edges they reserve
+    preserve_stacking: bool,
+    toggles_on: HashSet<(String, String)>, // ToggleCommand pairs whose on_cmd ran last
+    // Which workspace each managed window is on, so finding a window doesn't scan
+    // every workspace. Kept in step with the workspaces' window lists.
+    window_workspace: HashMap<Window, usize>,
+    tiling_frozen: bool, // ToggleTiling: windows keep their geometry until unfrozen
+    fullscreen: Option<Window>,
+    mod_mask: ModMask, // Held with a mouse button to drag floating windows
+    wm_protocols: Atom,
+    wm_delete_window: Atom,
+    net_wm_name: Atom,
+    drag: Option<Drag>,
+    corner_radius: u16, // 0 when disabled or the server lacks the Shape extension
+    // ShowWindowIds overlay: label window -> its text, and when the labels went up
+    id_labels: HashMap<Window, String>,
+    id_labels_shown: Option<Instant>,
+    confirm_quit: bool,
+    pending_quit: Option<Instant>,
+    workspace_wrap: bool,
+    focus_follows_mouse: bool,
+    dwindle_ratio: f32,
+    master_ratio: f32, // Starting master ratio of every workspace, restored by ResetLayout
+    gaps: u16,
+    smart_gaps: bool,
+    outer_gap: u16,
+    border_width: u16,
+    focused_border: u32, // Border pixel values
+    unfocused_border: u32,
+    overview: bool, // Every workspace's windows shown together in a grid
+    on_enter: HashMap<usize, String>,
+    on_empty_workspace: Option<String>,
+    tiled_geometry: HashMap<Window, Rect>, // Last geometry the layout gave each window
+    snapped_at: HashMap<Window, Instant>,
+    // Unmaps we requested whose UnmapNotify hasn't arrived yet, per window
+    pending_unmaps: HashMap<Window, u32>,
 }
 
 impl WindowManager {
@@ -31,38 +136,248 @@ impl WindowManager {
         conn: &C,
         screen: &Screen,
         config: Config,
+        mod_mask: ModMask,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         let mut workspaces = Vec::new();
-        for _ in 0..9 {
-            workspaces.push(Workspace::new());
+        let master_ratio = config
+            .master_ratio
+            .clamp(layout::MIN_MASTER_RATIO, layout::MAX_MASTER_RATIO);
+        for layout in default_layouts(&config, workspace_count(&config)) {
+            workspaces.push(Workspace::new(master_ratio, layout));
         }
 
-        let bar = Bar::new(conn, screen, config.bar.clone())?;
+        let bar = Bar::new(conn, screen, config.bar.clone(), config.bar_position)?;
+        let bar_gap = bar.height();
+        let ewmh = Ewmh::new(conn, screen)?;
+        ewmh.set_desktops(conn, &desktop_names(&config, workspaces.len()))?;
+
+        let on_enter = on_enter_hooks(&config, workspaces.len());
+        let corner_radius = corner_radius(conn, &config)?;
+        let (focused_border, unfocused_border) = border_pixels(&bar, &config);
+
+        let wm_protocols = conn.intern_atom(false, b"WM_PROTOCOLS")?.reply()?.atom;
+        let wm_delete_window = conn.intern_atom(false, b"WM_DELETE_WINDOW")?.reply()?.atom;
+        let net_wm_name = conn.intern_atom(false, b"_NET_WM_NAME")?.reply()?.atom;
+
+        let active_workspace_idx = match config.default_workspace {
+            n if (1..=workspaces.len()).contains(&n) => n - 1,
+            n => {
+                log::warn!("default_workspace {} is out of range, using 1", n);
+                0
+            }
+        };
+
+        let mut monitors = query_monitors(conn, screen)?;
+        monitors.truncate(workspaces.len());
+        log::info!("Monitors: {:?}", monitors);
+        // The first monitor shows the default workspace, the others the lowest free ones
+        let monitor_workspace = std::iter::once(active_workspace_idx)
+            .chain((0..workspaces.len()).filter(|&i| i != active_workspace_idx))
+            .take(monitors.len())
+            .collect();
 
         let mut wm = Self {
             workspaces,
-            active_workspace_idx: 0,
+            active_workspace_idx,
+            previous_workspace_idx: None,
+            monitors,
+            monitor_workspace,
+            active_monitor: 0,
             focused_window: None,
+            previous_focus: None,
             bar,
+            ewmh,
             screen_width: screen.width_in_pixels,
             screen_height: screen.height_in_pixels,
             root: screen.root,
-            current_top_gap: 20,
+            bar_gap,
+            bar_position: config.bar_position,
             pending_split: SplitAxis::Vertical,
             last_mouse_pos: None,
+            spawn_target: None,
+            presentation: config.presentation.clone(),
+            presentation_snapshot: None,
+            promote_when_master: config.promote_when_master,
+            stack_layout: config.stack_layout,
+            external_bar_class: config.external_bar_class.clone(),
+            external_bar: None,
+            docks: HashMap::new(),
+            preserve_stacking: config.preserve_stacking,
+            toggles_on: HashSet::new(),
+            window_workspace: HashMap::new(),
+            tiling_frozen: false,
+            fullscreen: None,
+            mod_mask,
+            drag: None,
+            wm_protocols,
+            wm_delete_window,
+            net_wm_name,
+            corner_radius,
+            id_labels: HashMap::new(),
+            id_labels_shown: None,
+            confirm_quit: config.confirm_quit,
+            pending_quit: None,
+            workspace_wrap: config.workspace_wrap,
+            focus_follows_mouse: config.focus_follows_mouse,
+            dwindle_ratio: config
+                .dwindle_ratio
+                .clamp(layout::MIN_SPLIT_RATIO, layout::MAX_SPLIT_RATIO),
+            master_ratio,
+            gaps: config.gaps,
+            smart_gaps: config.smart_gaps,
+            outer_gap: config.outer_gap,
+            border_width: config.border_width,
+            focused_border,
+            unfocused_border,
+            overview: false,
+            on_enter,
+            on_empty_workspace: config.on_empty_workspace.clone(),
+            tiled_geometry: HashMap::new(),
+            snapped_at: HashMap::new(),
+            pending_unmaps: HashMap::new(),
         };
 
+        wm.ewmh.set_current_desktop(conn, wm.active_workspace_idx)?;
+
         // Initial Draw
+        wm.bar.update_modules();
         wm.update_bar(conn)?;
 
         Ok(wm)
     }
 
+    // Applies a reloaded config. Windows, workspaces and their layouts and ratios stay as
+    // they are, so workspace_count is not applied; master_ratio only changes what
+    // ResetLayout goes back to.
+    pub fn reload_config<C: Connection>(
+        &mut self,
+        conn: &C,
+        config: Config,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if workspace_count(&config) != self.workspaces.len() {
+            log::warn!("workspace_count changes take effect after restarting rwm");
+        }
+        self.bar.reload(conn, config.bar.clone())?;
+        self.ewmh
+            .set_desktops(conn, &desktop_names(&config, self.workspaces.len()))?;
+        self.bar.set_position(conn, config.bar_position)?;
+        self.bar_position = config.bar_position;
+        self.on_enter = on_enter_hooks(&config, self.workspaces.len());
+        // Workspaces still in their old default layout follow the new one
+        let layouts = default_layouts(&config, self.workspaces.len());
+        for (ws, layout) in self.workspaces.iter_mut().zip(layouts) {
+            if ws.layout == ws.default_layout {
+                ws.layout = layout;
+            }
+            ws.default_layout = layout;
+        }
+        self.corner_radius = corner_radius(conn, &config)?;
+        (self.focused_border, self.unfocused_border) = border_pixels(&self.bar, &config);
+        self.presentation = config.presentation;
+        self.promote_when_master = config.promote_when_master;
+        self.stack_layout = config.stack_layout;
+        self.external_bar_class = config.external_bar_class;
+        self.preserve_stacking = config.preserve_stacking;
+        self.confirm_quit = config.confirm_quit;
+        self.workspace_wrap = config.workspace_wrap;
+        // Presentation mode keeps click-to-focus; the new setting applies on leaving it
+        if let Some(snapshot) = &mut self.presentation_snapshot {
+            snapshot.focus_follows_mouse = config.focus_follows_mouse;
+        }
+        let presenting = self.presentation_snapshot.is_some() && self.presentation.click_to_focus;
+        self.set_focus_follows_mouse(conn, config.focus_follows_mouse && !presenting)?;
+        self.dwindle_ratio = config
+            .dwindle_ratio
+            .clamp(layout::MIN_SPLIT_RATIO, layout::MAX_SPLIT_RATIO);
+        self.master_ratio = config
+            .master_ratio
+            .clamp(layout::MIN_MASTER_RATIO, layout::MAX_MASTER_RATIO);
+        self.gaps = config.gaps;
+        self.smart_gaps = config.smart_gaps;
+        self.outer_gap = config.outer_gap;
+        self.border_width = config.border_width;
+        self.on_empty_workspace = config.on_empty_workspace;
+
+        let unfocused = ChangeWindowAttributesAux::new().border_pixel(self.unfocused_border);
+        for &window in self.window_workspace.keys() {
+            if Some(window) != self.focused_window {
+                conn.change_window_attributes(window, &unfocused)?;
+            }
+        }
+        if let Some(window) = self.focused_window {
+            let focused = ChangeWindowAttributesAux::new().border_pixel(self.focused_border);
+            conn.change_window_attributes(window, &focused)?;
+        }
+
+        self.refresh_layout(conn)?;
+        self.bar.update_modules();
+        self.update_bar(conn)?;
+        Ok(())
+    }
+
+    // EWMH requests from pagers and taskbars
+    pub fn handle_client_message<C: Connection>(
+        &mut self,
+        conn: &C,
+        event: ClientMessageEvent,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match self.ewmh.parse_request(&event) {
+            Some(ewmh::Request::CurrentDesktop(index)) => self.switch_workspace(conn, index),
+            Some(ewmh::Request::ActiveWindow(window)) => self.activate_window(conn, window),
+            Some(ewmh::Request::Fullscreen(window, change)) => {
+                if !self.window_workspace.contains_key(&window) {
+                    return Ok(());
+                }
+                let fullscreen = match change {
+                    ewmh::StateChange::Remove => false,
+                    ewmh::StateChange::Add => true,
+                    ewmh::StateChange::Toggle => self.fullscreen != Some(window),
+                };
+                self.set_fullscreen(conn, window, fullscreen)
+            }
+            Some(ewmh::Request::Above(window, change)) => {
+                let Some(&idx) = self.window_workspace.get(&window) else {
+                    return Ok(());
+                };
+                let above = match change {
+                    ewmh::StateChange::Remove => false,
+                    ewmh::StateChange::Add => true,
+                    ewmh::StateChange::Toggle => !self.workspaces[idx].above.contains(&window),
+                };
+                self.set_above(conn, window, idx, above)
+            }
+            None => Ok(()),
+        }
+    }
+
+    // Brings a managed window into view and focuses it. Unmanaged windows are ignored.
+    fn activate_window<C: Connection>(
+        &mut self,
+        conn: &C,
+        window: Window,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(&idx) = self.window_workspace.get(&window) else {
+            return Ok(());
+        };
+        match self.monitor_of(idx) {
+            Some(monitor) => self.focus_monitor(conn, monitor)?,
+            None => self.switch_workspace(conn, idx)?,
+        }
+        self.set_focus(conn, window)
+    }
+
     pub fn handle_timer_tick<C: Connection>(
         &mut self,
         conn: &C,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        self.bar.update_modules();
         self.update_bar(conn)?;
+        if self
+            .id_labels_shown
+            .is_some_and(|shown| shown.elapsed() > WINDOW_ID_TIMEOUT)
+        {
+            self.clear_window_ids(conn)?;
+        }
         Ok(())
     }
 
@@ -73,21 +388,56 @@ impl WindowManager {
         // 1. Get Layout String
         let active_ws = &self.workspaces[self.active_workspace_idx];
         let layout_str = match active_ws.layout {
-            Layout::MasterStack => "[Master]".to_string(),
+            Layout::MasterStack => {
+                format!("[Master {}%]", (active_ws.master_ratio * 100.0).round())
+            }
             Layout::VerticalStack => "[Vertical]".to_string(),
-            Layout::Monocle => "[Monocle]".to_string(),
+            Layout::Grid => "[Grid]".to_string(),
+            Layout::Spiral => "[Spiral]".to_string(),
+            Layout::CenteredMaster => "[Centered]".to_string(),
+            Layout::Tabbed => "[Tabbed]".to_string(),
+            Layout::Monocle => {
+                // Show which of the stacked windows is visible, e.g. [Monocle 2/5]
+                let count = active_ws.windows.len();
+                let focused_pos = self
+                    .focused_window
+                    .and_then(|w| active_ws.windows.iter().position(|&win| win == w));
+                match focused_pos {
+                    Some(pos) => format!("[Monocle {}/{}]", pos + 1, count),
+                    None => "[Monocle]".to_string(),
+                }
+            }
             Layout::Dwindle => match self.pending_split {
                 SplitAxis::Vertical => "[Dwindle -]".to_string(),
                 SplitAxis::Horizontal => "[Dwindle |]".to_string(),
             },
         };
 
+        let layout_str = if self.quit_pending() {
+            "[Quit again to exit]".to_string()
+        } else {
+            layout_str
+        };
+
+        // Tabbed gets a tab per tiled window in place of the title
+        let tabs: Vec<Window> = if active_ws.layout == Layout::Tabbed {
+            active_ws
+                .windows
+                .iter()
+                .copied()
+                .filter(|w| !active_ws.floating.contains(w))
+                .collect()
+        } else {
+            Vec::new()
+        };
+
         self.bar.draw(
             conn,
             self.active_workspace_idx,
             self.workspaces.len(),
             &layout_str,
             self.focused_window,
+            &tabs,
         )?;
         Ok(())
     }
@@ -97,14 +447,10 @@ impl WindowManager {
         conn: &C,
         window: Window,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let existing_ws_idx = self
-            .workspaces
-            .iter()
-            .position(|ws| ws.windows.contains(&window));
-
-        if let Some(idx) = existing_ws_idx {
-            if idx != self.active_workspace_idx {
-                self.switch_workspace(conn, idx)?;
+        if let Some(&idx) = self.window_workspace.get(&window) {
+            match self.monitor_of(idx) {
+                Some(monitor) => self.focus_monitor(conn, monitor)?,
+                None => self.switch_workspace(conn, idx)?,
             }
 
             conn.map_window(window)?;
@@ -114,14 +460,60 @@ impl WindowManager {
             return Ok(());
         }
 
-        let active_ws = &mut self.workspaces[self.active_workspace_idx];
-        active_ws.windows.push(window);
-        active_ws.split_history.push(self.pending_split);
+        if self.adopt_external_bar(conn, window)? || self.adopt_dock(conn, window)? {
+            return Ok(());
+        }
+
+        // A pending SpawnAndView claims the next new window for its workspace
+        let target_idx = self
+            .take_spawn_target()
+            .unwrap_or(self.active_workspace_idx);
 
-        let changes = ChangeWindowAttributesAux::new().event_mask(
-            EventMask::ENTER_WINDOW | EventMask::STRUCTURE_NOTIFY | EventMask::PROPERTY_CHANGE,
+        // A window that maps already advertising _NET_WM_STATE_ABOVE starts always-on-top
+        let above = self.ewmh.wants_above(conn, window)?;
+        let floating = self.ewmh.wants_floating(conn, window)?;
+
+        let entry = WindowEntry {
+            split: Some((self.pending_split, self.dwindle_ratio)),
+            above,
+            floating,
+        };
+        add_window(
+            &mut self.workspaces,
+            &mut self.window_workspace,
+            window,
+            target_idx,
+            entry,
         );
+        self.ewmh.add_client(conn, window)?;
+        self.ewmh.set_window_desktop(conn, window, target_idx)?;
+
+        let changes = ChangeWindowAttributesAux::new()
+            .event_mask(
+                EventMask::ENTER_WINDOW | EventMask::STRUCTURE_NOTIFY | EventMask::PROPERTY_CHANGE,
+            )
+            .border_pixel(self.unfocused_border);
         conn.change_window_attributes(window, &changes)?;
+        self.grab_buttons(conn, window)?;
+        if floating {
+            self.center_window(conn, window, target_idx)?;
+        }
+
+        // Video players and games may map already asking to be fullscreen
+        if self.ewmh.wants_fullscreen(conn, window)? {
+            self.set_fullscreen(conn, window, true)?;
+        }
+
+        // A window placed on another workspace doesn't pull focus or the view away from
+        // the current one. It stays unmapped unless that workspace is on another monitor.
+        if target_idx != self.active_workspace_idx {
+            if self.monitor_of(target_idx).is_some() {
+                conn.map_window(window)?;
+                self.refresh_layout(conn)?;
+            }
+            log::info!("Placed window {} on workspace {}", window, target_idx + 1);
+            return Ok(());
+        }
 
         conn.map_window(window)?;
         self.set_focus(conn, window)?;
@@ -130,6 +522,41 @@ impl WindowManager {
         Ok(())
     }
 
+    // Centers a floating window at its requested size on the monitor showing the
+    // workspace, or on the active monitor if the workspace is hidden
+    fn center_window<C: Connection>(
+        &self,
+        conn: &C,
+        window: Window,
+        workspace_idx: usize,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let monitor = self.monitors[self
+            .monitor_of(workspace_idx)
+            .unwrap_or(self.active_monitor)];
+        let geometry = conn.get_geometry(window)?.reply()?;
+        let x = monitor.x + (monitor.width as i32 - geometry.width as i32) / 2;
+        let y = monitor.y + (monitor.height as i32 - geometry.height as i32) / 2;
+        let changes = ConfigureWindowAux::new()
+            .x(x)
+            .y(y)
+            .border_width(self.border_width as u32);
+        conn.configure_window(window, &changes)?;
+        Ok(())
+    }
+
+    pub fn set_spawn_target(&mut self, index: usize) {
+        if index < self.workspaces.len() {
+            self.spawn_target = Some((index, Instant::now()));
+        }
+    }
+
+    fn take_spawn_target(&mut self) -> Option<usize> {
+        self.spawn_target
+            .take()
+            .filter(|(_, requested)| requested.elapsed() < SPAWN_TARGET_TIMEOUT)
+            .map(|(index, _)| index)
+    }
+
     pub fn handle_expose<C: Connection>(
         &mut self,
         conn: &C,
@@ -137,6 +564,39 @@ impl WindowManager {
     ) -> Result<(), Box<dyn std::error::Error>> {
         if event.window == self.bar.window {
             self.update_bar(conn)?;
+        } else if let Some(text) = self.id_labels.get(&event.window) {
+            self.bar.draw_label(conn, event.window, text)?;
+        }
+        Ok(())
+    }
+
+    // Redraws the bar when a window whose title it shows renames itself: the focused
+    // window while titles are shown, or any tab in Tabbed
+    pub fn handle_property_notify<C: Connection>(
+        &mut self,
+        conn: &C,
+        event: PropertyNotifyEvent,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // Docks such as polybar change their strut when resized or moved
+        if self.docks.contains_key(&event.window) {
+            if self.ewmh.is_strut_property(event.atom) {
+                let strut = self.ewmh.strut(conn, event.window)?;
+                self.docks.insert(event.window, strut);
+                self.refresh_layout(conn)?;
+            }
+            return Ok(());
+        }
+        if event.atom != u32::from(AtomEnum::WM_NAME) && event.atom != self.net_wm_name {
+            return Ok(());
+        }
+        let active_ws = &self.workspaces[self.active_workspace_idx];
+        let shown = if active_ws.layout == Layout::Tabbed {
+            active_ws.windows.contains(&event.window)
+        } else {
+            self.bar.show_title && self.focused_window == Some(event.window)
+        };
+        if shown {
+            self.update_bar(conn)?;
         }
         Ok(())
     }
@@ -150,17 +610,42 @@ impl WindowManager {
             return Ok(());
         }
 
-        if let Some(last) = self.last_mouse_pos {
-            if last == (event.root_x, event.root_y) {
-                return Ok(());
-            }
+        if !self.focus_follows_mouse {
+            return Ok(());
+        }
+
+        if let Some(last) = self.last_mouse_pos
+            && last == (event.root_x, event.root_y)
+        {
+            return Ok(());
         }
 
         self.last_mouse_pos = Some((event.root_x, event.root_y));
+        self.focus_pointed_window(conn, event.event)
+    }
 
-        let active_ws = &self.workspaces[self.active_workspace_idx];
-        if active_ws.windows.contains(&event.event) {
-            self.set_focus(conn, event.event)?;
+    // Focuses a window the pointer entered or clicked. In the overview any workspace's
+    // window can be picked. Otherwise any visible one can, and picking a window on
+    // another monitor makes that monitor active.
+    fn focus_pointed_window<C: Connection>(
+        &mut self,
+        conn: &C,
+        window: Window,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let focusable = if self.overview {
+            self.window_workspace.contains_key(&window)
+        } else if let Some(monitor) = self
+            .window_workspace
+            .get(&window)
+            .and_then(|&idx| self.monitor_of(idx))
+        {
+            self.focus_monitor(conn, monitor)?;
+            true
+        } else {
+            false
+        };
+        if focusable {
+            self.set_focus(conn, window)?;
         }
         Ok(())
     }
@@ -170,34 +655,70 @@ impl WindowManager {
         conn: &C,
         window: Window,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        for (i, ws) in self.workspaces.iter_mut().enumerate() {
-            if let Some(pos) = ws.windows.iter().position(|&w| w == window) {
-                ws.windows.remove(pos);
-                if pos < ws.split_history.len() {
-                    ws.split_history.remove(pos);
-                }
-
-                if i == self.active_workspace_idx {
-                    self.refresh_layout(conn)?;
-                }
+        if let Some((i, _)) = take_window(&mut self.workspaces, &mut self.window_workspace, window)
+        {
+            self.ewmh.remove_client(conn, window)?;
 
-                break;
+            if self.monitor_of(i).is_some() {
+                self.refresh_layout(conn)?;
+                self.update_bar(conn)?;
+                // The re-tile slides a neighbour under the pointer. Without this, the
+                // resulting EnterNotify would steal focus from the still-focused window.
+                self.sync_mouse_pos(conn)?;
             }
         }
 
+        if self.previous_focus == Some(window) {
+            self.previous_focus = None;
+        }
+        self.snapped_at.remove(&window);
+        self.pending_unmaps.remove(&window);
+        if self.docks.remove(&window).is_some() {
+            self.refresh_layout(conn)?;
+        }
+        if self.external_bar == Some(window) {
+            self.external_bar = None;
+        }
+        if self.fullscreen == Some(window) {
+            self.fullscreen = None;
+        }
+        if self.drag.as_ref().is_some_and(|drag| drag.window == window) {
+            self.drag = None;
+        }
         if self.focused_window == Some(window) {
             let active_ws = &self.workspaces[self.active_workspace_idx];
             if let Some(&new_focus) = active_ws.windows.last() {
                 self.set_focus(conn, new_focus)?;
             } else {
-                self.focused_window = None;
-                conn.set_input_focus(InputFocus::POINTER_ROOT, self.root, 0u32)?;
+                self.clear_focus(conn)?;
             }
         }
 
         Ok(())
     }
 
+    // Records where the pointer currently is, so EnterNotify events caused by windows moving
+    // underneath a stationary pointer are ignored by handle_enter_notify.
+    fn sync_mouse_pos<C: Connection>(
+        &mut self,
+        conn: &C,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let pointer = conn.query_pointer(self.root)?.reply()?;
+        self.last_mouse_pos = Some((pointer.root_x, pointer.root_y));
+        Ok(())
+    }
+
+    // Jumps back to the workspace the last switch came from
+    pub fn toggle_workspace<C: Connection>(
+        &mut self,
+        conn: &C,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match self.previous_workspace_idx {
+            Some(idx) => self.switch_workspace(conn, idx),
+            None => Ok(()),
+        }
+    }
+
     pub fn switch_workspace<C: Connection>(
         &mut self,
         conn: &C,
@@ -206,186 +727,1100 @@ impl WindowManager {
         if index == self.active_workspace_idx || index >= self.workspaces.len() {
             return Ok(());
         }
+        if self.overview {
+            self.toggle_overview(conn)?;
+        }
 
         let old_idx = self.active_workspace_idx;
+        if self.preserve_stacking {
+            self.save_stacking(conn, old_idx)?;
+        }
+        // A workspace already shown on another monitor trades places with the current one
+        let other_monitor = self.monitor_of(index);
+        if let Some(monitor) = other_monitor {
+            self.monitor_workspace[monitor] = old_idx;
+        }
+        self.monitor_workspace[self.active_monitor] = index;
         self.active_workspace_idx = index;
+        self.previous_workspace_idx = Some(old_idx);
+        self.ewmh.set_current_desktop(conn, index)?;
         self.refresh_layout(conn)?;
 
         // Show new workspace
         for window in &self.workspaces[self.active_workspace_idx].windows {
             conn.map_window(*window)?;
         }
+        if self.preserve_stacking {
+            self.restore_stacking(conn)?;
+        }
 
-        // Hide previous workspace
-        for window in &self.workspaces[old_idx].windows {
-            conn.unmap_window(*window)?;
+        // Hide previous workspace, unless it moved to the other monitor
+        if other_monitor.is_none() {
+            for window in self.workspaces[old_idx].windows.clone() {
+                self.hide_window(conn, window)?;
+            }
         }
 
         self.update_bar(conn)?;
 
-        // Focus workspace
-        if let Some(&window) = self.workspaces[self.active_workspace_idx].windows.last() {
+        if let Some(command) = self.on_enter.get(&index) {
+            crate::spawn(command);
+        }
+        if self.workspaces[index].windows.is_empty()
+            && let Some(command) = &self.on_empty_workspace
+        {
+            crate::spawn(command);
+        }
+
+        // Focus workspace. With a saved stacking order, the topmost window gets focus so
+        // raising it doesn't disturb the order.
+        let ws = &self.workspaces[self.active_workspace_idx];
+        let topmost = if self.preserve_stacking {
+            ws.stacking.iter().rev().find(|w| ws.windows.contains(w))
+        } else {
+            None
+        };
+        if let Some(&window) = topmost.or(ws.windows.last()) {
             self.set_focus(conn, window)?;
         } else {
-            self.focused_window = None;
-            conn.set_input_focus(InputFocus::POINTER_ROOT, self.root, 0u32)?;
+            self.clear_focus(conn)?;
         }
 
         Ok(())
     }
 
-    pub fn move_window_to_workspace<C: Connection>(
+    // Unmaps a managed window, remembering that the UnmapNotify it causes is ours so
+    // handle_unmap_notify doesn't take it for the client withdrawing the window.
+    // Windows that aren't mapped produce no event, so they aren't counted.
+    fn hide_window<C: Connection>(
         &mut self,
         conn: &C,
-        target_index: usize,
+        window: Window,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        if target_index == self.active_workspace_idx || target_index >= self.workspaces.len() {
+        let attrs = conn.get_window_attributes(window)?.reply()?;
+        if attrs.map_state == MapState::UNMAPPED {
             return Ok(());
         }
-        if let Some(window) = self.focused_window {
-            let active_ws = &mut self.workspaces[self.active_workspace_idx];
-            let mut split_preference = SplitAxis::Vertical;
+        *self.pending_unmaps.entry(window).or_insert(0) += 1;
+        conn.unmap_window(window)?;
+        Ok(())
+    }
 
-            if let Some(pos) = active_ws.windows.iter().position(|&w| w == window) {
-                active_ws.windows.remove(pos);
-                if pos < active_ws.split_history.len() {
-                    split_preference = active_ws.split_history.remove(pos);
-                }
+    // A managed window unmapped by its client is forgotten, like a destroyed one. If it
+    // maps again it comes back through handle_map_request as a new window.
+    pub fn handle_unmap_notify<C: Connection>(
+        &mut self,
+        conn: &C,
+        event: UnmapNotifyEvent,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // The root's SUBSTRUCTURE_NOTIFY reports the same unmap again; handle it once
+        if event.event != event.window {
+            return Ok(());
+        }
+        if let Some(pending) = self.pending_unmaps.get_mut(&event.window) {
+            *pending -= 1;
+            if *pending == 0 {
+                self.pending_unmaps.remove(&event.window);
             }
-
-            conn.unmap_window(window)?;
-            self.workspaces[target_index].windows.push(window);
-            self.workspaces[target_index]
-                .split_history
-                .push(split_preference);
+            return Ok(());
+        }
+        // A hidden dock gives its space back; it is adopted again if it maps again
+        if self.docks.remove(&event.window).is_some() {
+            log::info!("Dock {} unmapped", event.window);
             self.refresh_layout(conn)?;
+            return Ok(());
+        }
+        if self.window_workspace.contains_key(&event.window) {
+            log::info!("Window {} withdrawn by its client", event.window);
+            self.handle_destroy_notify(conn, event.window)?;
+        }
+        Ok(())
+    }
 
-            let active_ws = &self.workspaces[self.active_workspace_idx];
-            if let Some(&last) = active_ws.windows.last() {
-                self.set_focus(conn, last)?;
-            } else {
-                self.focused_window = None;
-                conn.set_input_focus(InputFocus::POINTER_ROOT, self.root, 0u32)?;
-            }
+    // The monitor showing the given workspace, if it's visible
+    fn monitor_of(&self, workspace_idx: usize) -> Option<usize> {
+        self.monitor_workspace
+            .iter()
+            .position(|&idx| idx == workspace_idx)
+    }
 
-            self.refresh_layout(conn)?;
-            self.update_bar(conn)?;
+    fn focus_monitor<C: Connection>(
+        &mut self,
+        conn: &C,
+        monitor: usize,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if monitor != self.active_monitor {
+            log::info!("Monitor {} active", monitor + 1);
+            self.active_monitor = monitor;
+            self.active_workspace_idx = self.monitor_workspace[monitor];
+            self.ewmh
+                .set_current_desktop(conn, self.active_workspace_idx)?;
         }
         Ok(())
     }
 
-    pub fn cycle_layout<C: Connection>(
+    // Records the X stacking order of a workspace's windows, bottom to top
+    fn save_stacking<C: Connection>(
         &mut self,
         conn: &C,
+        idx: usize,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let active_ws = &mut self.workspaces[self.active_workspace_idx];
-        active_ws.layout = match active_ws.layout {
-            Layout::MasterStack => Layout::VerticalStack,
-            Layout::VerticalStack => Layout::Dwindle,
-            Layout::Dwindle => Layout::Monocle,
-            Layout::Monocle => Layout::MasterStack,
-        };
-        // Changing layout might require restacking so refocus to ensure focused window stays on
-        // top if needed
-        if let Some(win) = self.focused_window {
-            self.set_focus(conn, win)?;
-        }
-        self.update_bar(conn)?;
-        self.refresh_layout(conn)?;
+        let tree = conn.query_tree(self.root)?.reply()?;
+        let ws = &mut self.workspaces[idx];
+        ws.stacking = tree
+            .children
+            .into_iter()
+            .filter(|w| ws.windows.contains(w))
+            .collect();
         Ok(())
     }
 
-    pub fn cycle_focus<C: Connection>(
+    // Re-raises the active workspace's windows in their saved order, then puts the
+    // always-on-top windows back above them. Windows added since are left where they are.
+    fn restore_stacking<C: Connection>(&self, conn: &C) -> Result<(), Box<dyn std::error::Error>> {
+        let ws = &self.workspaces[self.active_workspace_idx];
+        let values = ConfigureWindowAux::new().stack_mode(StackMode::ABOVE);
+        for &window in ws.stacking.iter().filter(|w| ws.windows.contains(w)) {
+            conn.configure_window(window, &values)?;
+        }
+        self.raise_above_windows(conn)
+    }
+
+    pub fn move_window_to_workspace<C: Connection>(
         &mut self,
         conn: &C,
-        dir: FocusDirection,
+        target_index: usize,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let active_ws = &mut self.workspaces[self.active_workspace_idx];
-        if active_ws.windows.is_empty() {
+        if target_index == self.active_workspace_idx || target_index >= self.workspaces.len() {
             return Ok(());
         }
+        if let Some(window) = self.focused_window {
+            // Per-window workspace state moves with the window so the source
+            // workspace keeps no stale references to it
+            if self.window_workspace.get(&window) != Some(&self.active_workspace_idx) {
+                return Ok(());
+            }
+            let Some((_, entry)) =
+                take_window(&mut self.workspaces, &mut self.window_workspace, window)
+            else {
+                return Ok(());
+            };
+            let entry = WindowEntry {
+                split: entry
+                    .split
+                    .or(Some((SplitAxis::Vertical, self.dwindle_ratio))),
+                ..entry
+            };
+
+            // The window stays mapped if its new workspace is on another monitor
+            if self.monitor_of(target_index).is_none() {
+                self.hide_window(conn, window)?;
+            }
+            add_window(
+                &mut self.workspaces,
+                &mut self.window_workspace,
+                window,
+                target_index,
+                entry,
+            );
+            self.ewmh.set_window_desktop(conn, window, target_index)?;
+            self.refresh_layout(conn)?;
 
-        // Find the index of the currently focused window
-        let current_index = match self.focused_window {
-            Some(w) => active_ws.windows.iter().position(|&win| win == w),
-            None => None,
-        };
-
-        // Calculate the next index
-        let next_index = match current_index {
-            Some(i) => match dir {
-                FocusDirection::Next => (i + 1) % active_ws.windows.len(),
-                // Logic for wrappign backwards (e.g. 0 -> last)
-                FocusDirection::Prev => (i + active_ws.windows.len() - 1) % active_ws.windows.len(),
-            },
-            None => 0, // If nothing is focused, start at 0
-        };
+            let active_ws = &self.workspaces[self.active_workspace_idx];
+            if let Some(&last) = active_ws.windows.last() {
+                self.set_focus(conn, last)?;
+            } else {
+                self.clear_focus(conn)?;
+            }
 
-        // Set the focus
-        let next_window = active_ws.windows[next_index];
-        self.set_focus(conn, next_window)?;
-        self.update_bar(conn)?;
+            self.refresh_layout(conn)?;
+            self.update_bar(conn)?;
+        }
         Ok(())
     }
 
-    pub fn kill_focused_window<C: Connection>(
-        &self,
+    pub fn move_window_to_adjacent_workspace<C: Connection>(
+        &mut self,
         conn: &C,
+        dir: FocusDirection,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        // We only try to kill if we actually have a focused window
-        if let Some(window) = self.focused_window {
-            conn.kill_client(window)?;
+        if let Some(target) = self.adjacent_workspace(dir) {
+            self.move_window_to_workspace(conn, target)?;
         }
         Ok(())
     }
 
-    fn set_focus<C: Connection>(
+    // The workspace next to the active one, honouring workspace_wrap at the ends
+    fn adjacent_workspace(&self, dir: FocusDirection) -> Option<usize> {
+        let len = self.workspaces.len();
+        let idx = self.active_workspace_idx;
+        match dir {
+            FocusDirection::Next if idx + 1 < len => Some(idx + 1),
+            FocusDirection::Prev if idx > 0 => Some(idx - 1),
+            FocusDirection::Next if self.workspace_wrap => Some(0),
+            FocusDirection::Prev if self.workspace_wrap => Some(len - 1),
+            _ => None,
+        }
+    }
+
+    // Stops re-tiling on map, destroy and layout changes so windows can be arranged by
+    // hand. New windows keep the position they asked for. Unfreezing tiles everything.
+    pub fn toggle_tiling<C: Connection>(
         &mut self,
         conn: &C,
-        window: Window,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        self.focused_window = Some(window);
-        conn.set_input_focus(InputFocus::POINTER_ROOT, window, 0u32)?;
-        let values = ConfigureWindowAux::new().stack_mode(StackMode::ABOVE);
-        conn.configure_window(window, &values)?;
-        self.update_bar(conn)?;
-        Ok(())
+        self.tiling_frozen = !self.tiling_frozen;
+        log::info!("Tiling frozen: {}", self.tiling_frozen);
+        self.refresh_layout(conn)
     }
 
-    fn refresh_layout<C: Connection>(&self, conn: &C) -> Result<(), Box<dyn std::error::Error>> {
-        let active_ws = &self.workspaces[self.active_workspace_idx];
-        layout::apply_layout(
-            conn,
-            active_ws.layout,
-            &active_ws.windows,
-            self.screen_width,
-            self.screen_height,
-            self.current_top_gap,
-            &active_ws.split_history,
-        )
+    // Shows every managed window from every workspace in one grid. Leaving the overview
+    // goes to the workspace of whichever window was focused while it was open.
+    pub fn toggle_overview<C: Connection>(
+        &mut self,
+        conn: &C,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.overview {
+            self.overview = true;
+            for ws in &self.workspaces {
+                for &window in &ws.windows {
+                    conn.map_window(window)?;
+                }
+            }
+            self.refresh_layout(conn)?;
+            return Ok(());
+        }
+
+        self.overview = false;
+        if let Some(&idx) = self
+            .focused_window
+            .and_then(|w| self.window_workspace.get(&w))
+        {
+            match self.monitor_of(idx) {
+                Some(monitor) => self.active_monitor = monitor,
+                None => self.monitor_workspace[self.active_monitor] = idx,
+            }
+            self.active_workspace_idx = idx;
+            self.ewmh.set_current_desktop(conn, idx)?;
+        }
+        for i in 0..self.workspaces.len() {
+            if self.monitor_of(i).is_none() {
+                for window in self.workspaces[i].windows.clone() {
+                    self.hide_window(conn, window)?;
+                }
+            }
+        }
+        self.refresh_layout(conn)?;
+        if let Some(win) = self.focused_window {
+            self.set_focus(conn, win)?;
+        }
+        self.update_bar(conn)?;
+        Ok(())
+    }
+
+    pub fn cycle_layout<C: Connection>(
+        &mut self,
+        conn: &C,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let active_ws = &mut self.workspaces[self.active_workspace_idx];
+        active_ws.layout = match active_ws.layout {
+            Layout::MasterStack => Layout::VerticalStack,
+            Layout::VerticalStack => Layout::Dwindle,
+            Layout::Dwindle => Layout::Monocle,
+            Layout::Monocle => Layout::Grid,
+            Layout::Grid => Layout::Spiral,
+            Layout::Spiral => Layout::CenteredMaster,
+            Layout::CenteredMaster => Layout::Tabbed,
+            Layout::Tabbed => Layout::MasterStack,
+        };
+        self.layout_changed(conn)
+    }
+
+    pub fn cycle_layout_back<C: Connection>(
+        &mut self,
+        conn: &C,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let active_ws = &mut self.workspaces[self.active_workspace_idx];
+        active_ws.layout = match active_ws.layout {
+            Layout::MasterStack => Layout::Tabbed,
+            Layout::VerticalStack => Layout::MasterStack,
+            Layout::Dwindle => Layout::VerticalStack,
+            Layout::Monocle => Layout::Dwindle,
+            Layout::Grid => Layout::Monocle,
+            Layout::Spiral => Layout::Grid,
+            Layout::CenteredMaster => Layout::Spiral,
+            Layout::Tabbed => Layout::CenteredMaster,
+        };
+        self.layout_changed(conn)
+    }
+
+    fn layout_changed<C: Connection>(
+        &mut self,
+        conn: &C,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // Changing layout might require restacking so refocus to ensure focused window stays on
+        // top if needed
+        if let Some(win) = self.focused_window {
+            self.set_focus(conn, win)?;
+        }
+        self.update_bar(conn)?;
+        self.refresh_layout(conn)?;
+        Ok(())
+    }
+
+    pub fn reset_layout<C: Connection>(
+        &mut self,
+        conn: &C,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let active_ws = &mut self.workspaces[self.active_workspace_idx];
+        if active_ws.layout == active_ws.default_layout
+            && active_ws.master_ratio == self.master_ratio
+        {
+            return Ok(());
+        }
+        active_ws.layout = active_ws.default_layout;
+        active_ws.master_ratio = self.master_ratio;
+
+        if let Some(win) = self.focused_window {
+            self.set_focus(conn, win)?;
+        }
+        self.update_bar(conn)?;
+        self.refresh_layout(conn)?;
+        Ok(())
+    }
+
+    pub fn copy_layout_from<C: Connection>(
+        &mut self,
+        conn: &C,
+        source_index: usize,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if source_index == self.active_workspace_idx || source_index >= self.workspaces.len() {
+            return Ok(());
+        }
+        let source = &self.workspaces[source_index];
+        let (layout, master_ratio) = (source.layout, source.master_ratio);
+        let active_ws = &mut self.workspaces[self.active_workspace_idx];
+        active_ws.layout = layout;
+        active_ws.master_ratio = master_ratio;
+
+        if let Some(win) = self.focused_window {
+            self.set_focus(conn, win)?;
+        }
+        self.update_bar(conn)?;
+        self.refresh_layout(conn)?;
+        Ok(())
+    }
+
+    pub fn cycle_focus<C: Connection>(
+        &mut self,
+        conn: &C,
+        dir: FocusDirection,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let active_ws = &self.workspaces[self.active_workspace_idx];
+        let Some(next_window) = active_ws.cycle_target(self.focused_window, &dir) else {
+            return Ok(());
+        };
+        self.set_focus(conn, next_window)?;
+        self.update_bar(conn)?;
+        Ok(())
+    }
+
+    // Focuses the nearest window in the given direction from the focused one, going by
+    // where the windows actually are
+    pub fn focus_direction<C: Connection>(
+        &mut self,
+        conn: &C,
+        dir: Direction,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(focused) = self.focused_window else {
+            return Ok(());
+        };
+        let active_ws = &self.workspaces[self.active_workspace_idx];
+        if !active_ws.windows.contains(&focused) {
+            return Ok(());
+        }
+
+        let geometry = |window: Window| -> Result<Rect, Box<dyn std::error::Error>> {
+            let geom = conn.get_geometry(window)?.reply()?;
+            Ok(Rect {
+                x: geom.x as i32,
+                y: geom.y as i32,
+                width: geom.width as u32,
+                height: geom.height as u32,
+            })
+        };
+        let from = geometry(focused)?;
+        let mut candidates = Vec::new();
+        for &window in active_ws.windows.iter().filter(|&&w| w != focused) {
+            candidates.push((window, geometry(window)?));
+        }
+
+        if let Some(window) = nearest_in_direction(from, &candidates, &dir) {
+            self.set_focus(conn, window)?;
+            self.update_bar(conn)?;
+        }
+        Ok(())
+    }
+
+    pub fn focused_has_class<C: Connection>(
+        &self,
+        conn: &C,
+        class: &str,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        match self.focused_window {
+            Some(window) => Ok(window_class(conn, window)?.iter().any(|c| c == class)),
+            None => Ok(false),
+        }
+    }
+
+    // Runs on_cmd and off_cmd on alternate presses. Every pair starts off.
+    pub fn toggle_command(&mut self, on_cmd: &str, off_cmd: &str) {
+        let key = (on_cmd.to_string(), off_cmd.to_string());
+        if self.toggles_on.remove(&key) {
+            crate::spawn(off_cmd);
+        } else {
+            crate::spawn(on_cmd);
+            self.toggles_on.insert(key);
+        }
+    }
+
+    pub fn kill_focused_window<C: Connection>(
+        &self,
+        conn: &C,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // We only try to kill if we actually have a focused window
+        if let Some(window) = self.focused_window {
+            self.close_window(conn, window)?;
+        }
+        Ok(())
+    }
+
+    // Asks the window to close via WM_DELETE_WINDOW so it can prompt to save. Only
+    // windows that don't support the protocol are killed outright. Returns whether
+    // the window was killed.
+    fn close_window<C: Connection>(
+        &self,
+        conn: &C,
+        window: Window,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let protocols = conn
+            .get_property(false, window, self.wm_protocols, AtomEnum::ATOM, 0, 1024)?
+            .reply()?;
+        let supports_delete = protocols
+            .value32()
+            .is_some_and(|mut atoms| atoms.any(|atom| atom == self.wm_delete_window));
+
+        if !supports_delete {
+            conn.kill_client(window)?;
+            return Ok(true);
+        }
+
+        let event = ClientMessageEvent::new(
+            32,
+            window,
+            self.wm_protocols,
+            [self.wm_delete_window, x11rb::CURRENT_TIME, 0, 0, 0],
+        );
+        conn.send_event(false, window, EventMask::NO_EVENT, event)?;
+        Ok(false)
+    }
+
+    // Closes every window on the active workspace, leaving the other workspaces alone
+    pub fn clear_workspace<C: Connection>(
+        &mut self,
+        conn: &C,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // A killed window is forgotten now, cleaning up as it would on DestroyNotify; the
+        // later event finds nothing left to do. Windows asked to close stay managed until
+        // they go away, since they may prompt first. Focus is dropped so nothing is
+        // refocused while the workspace empties.
+        let windows = self.workspaces[self.active_workspace_idx].windows.clone();
+        self.focused_window = None;
+        for window in windows {
+            if self.close_window(conn, window)? {
+                self.handle_destroy_notify(conn, window)?;
+            }
+        }
+
+        self.clear_focus(conn)?;
+        self.update_bar(conn)?;
+        Ok(())
+    }
+
+    fn set_focus<C: Connection>(
+        &mut self,
+        conn: &C,
+        window: Window,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if self.focused_window != Some(window) {
+            self.previous_focus = self.focused_window;
+            if let Some(old) = self.focused_window {
+                let unfocused =
+                    ChangeWindowAttributesAux::new().border_pixel(self.unfocused_border);
+                conn.change_window_attributes(old, &unfocused)?;
+            }
+        }
+        let focused = ChangeWindowAttributesAux::new().border_pixel(self.focused_border);
+        conn.change_window_attributes(window, &focused)?;
+        self.focused_window = Some(window);
+        conn.set_input_focus(InputFocus::POINTER_ROOT, window, 0u32)?;
+        self.ewmh.set_active_window(conn, Some(window))?;
+        let values = ConfigureWindowAux::new().stack_mode(StackMode::ABOVE);
+        conn.configure_window(window, &values)?;
+        self.raise_above_windows(conn)?;
+        self.update_bar(conn)?;
+        Ok(())
+    }
+
+    // Leaves no window focused, with keyboard input following the pointer
+    fn clear_focus<C: Connection>(&mut self, conn: &C) -> Result<(), Box<dyn std::error::Error>> {
+        self.focused_window = None;
+        conn.set_input_focus(InputFocus::POINTER_ROOT, self.root, 0u32)?;
+        self.ewmh.set_active_window(conn, None)?;
+        Ok(())
+    }
+
+    fn refresh_layout<C: Connection>(
+        &mut self,
+        conn: &C,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // Window lists are still kept up to date while frozen; only placement waits
+        if self.tiling_frozen {
+            return Ok(());
+        }
+
+        let screen = Rect {
+            x: 0,
+            y: 0,
+            width: self.screen_width as u32,
+            height: self.screen_height as u32,
+        };
+        let mut placed = Vec::new();
+        let mut fullscreen = Vec::new();
+        if self.overview {
+            let area = self.tiling_area(screen);
+            let all_windows: Vec<Window> = self
+                .workspaces
+                .iter()
+                .flat_map(|ws| ws.windows.iter().copied())
+                .collect();
+            let gap = self.effective_gap(all_windows.len());
+            let slots =
+                layout::with_gaps(area, gap, |area| layout::tile_grid(all_windows.len(), area));
+            placed = layout::configure_windows(conn, &all_windows, &slots, self.border_width)?;
+        } else {
+            for (&monitor, &idx) in self.monitors.iter().zip(&self.monitor_workspace) {
+                let ws = &self.workspaces[idx];
+                // A fullscreen window covers its whole monitor, bar included, and the rest
+                // of the workspace stays where it was underneath it
+                if let Some(window) = self.fullscreen.filter(|w| ws.windows.contains(w)) {
+                    layout::configure_windows(conn, &[window], &[monitor], 0)?;
+                    if self.corner_radius > 0 {
+                        layout::round_corners(conn, window, monitor, 0)?;
+                    }
+                    fullscreen.push((window, monitor));
+                    continue;
+                }
+
+                let area = self.tiling_area(monitor);
+                let gap = self.effective_gap(ws.windows.len());
+                placed.extend(layout::apply_layout(
+                    conn,
+                    ws,
+                    area,
+                    self.stack_layout,
+                    gap,
+                    self.border_width,
+                )?);
+            }
+        }
+        if self.corner_radius > 0 {
+            for &(window, geometry) in &placed {
+                layout::round_corners(conn, window, geometry, self.corner_radius)?;
+            }
+        }
+        self.raise_above_windows(conn)?;
+        let values = ConfigureWindowAux::new().stack_mode(StackMode::ABOVE);
+        for &(window, _) in &fullscreen {
+            conn.configure_window(window, &values)?;
+        }
+        self.tiled_geometry = placed.into_iter().chain(fullscreen).collect();
+        Ok(())
+    }
+
+    // Gap between tiled windows, dropped for a lone window when smart_gaps is on
+    fn effective_gap(&self, window_count: usize) -> u16 {
+        if self.smart_gaps && window_count <= 1 {
+            0
+        } else {
+            self.gaps
+        }
+    }
+
+    // Part of a monitor available to tiled windows: clear of the bar and of docks' struts,
+    // inset by the outer gap. Both reserve space along the screen's edges, so only
+    // monitors reaching those edges lose space.
+    fn tiling_area(&self, monitor: Rect) -> Rect {
+        let outer = self.outer_gap as u32;
+        let (screen_width, screen_height) = (self.screen_width as i32, self.screen_height as i32);
+        let mut left = monitor.x;
+        let mut right = monitor.x + monitor.width as i32;
+        let mut top = monitor.y;
+        let mut bottom = monitor.y + monitor.height as i32;
+        match self.bar_position {
+            BarPosition::Top => top = top.max(self.bar_gap as i32),
+            BarPosition::Bottom => bottom = bottom.min(screen_height - self.bar_gap as i32),
+        }
+        for strut in self.docks.values() {
+            left = left.max(strut.left as i32);
+            right = right.min(screen_width - strut.right as i32);
+            top = top.max(strut.top as i32);
+            bottom = bottom.min(screen_height - strut.bottom as i32);
+        }
+        let width = (right - left).max(1) as u32;
+        let height = (bottom - top).max(1) as u32;
+        Rect {
+            x: left + outer as i32,
+            y: top + outer as i32,
+            width: width.saturating_sub(2 * outer).max(1),
+            height: height.saturating_sub(2 * outer).max(1),
+        }
+    }
+
+    // Floating and unmanaged windows get the geometry they ask for. A tiled window keeps
+    // its slot and is told so with a synthetic ConfigureNotify, as ICCCM expects.
+    pub fn handle_configure_request<C: Connection>(
+        &mut self,
+        conn: &C,
+        event: ConfigureRequestEvent,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let window = event.window;
+        let slot = self
+            .tiled_geometry
+            .get(&window)
+            .filter(|_| !self.tiling_frozen);
+        let Some(&slot) = slot else {
+            let changes = ConfigureWindowAux::from_configure_request(&event);
+            conn.configure_window(window, &changes)?;
+            return Ok(());
+        };
+
+        let border_width = if self.fullscreen == Some(window) {
+            0
+        } else {
+            self.border_width
+        };
+        let notify = ConfigureNotifyEvent {
+            response_type: xproto::CONFIGURE_NOTIFY_EVENT,
+            sequence: 0,
+            event: window,
+            window,
+            above_sibling: x11rb::NONE,
+            x: slot.x as i16,
+            y: slot.y as i16,
+            width: slot.width as u16,
+            height: slot.height as u16,
+            border_width,
+            override_redirect: false,
+        };
+        conn.send_event(false, window, EventMask::STRUCTURE_NOTIFY, notify)?;
+        Ok(())
+    }
+
+    // Snaps a tiled window back into its slot if it ends up with different geometry
+    pub fn handle_configure_notify<C: Connection>(
+        &mut self,
+        conn: &C,
+        event: ConfigureNotifyEvent,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // The root's SUBSTRUCTURE_NOTIFY reports the same change again; handle it once
+        if event.event != event.window || self.tiling_frozen {
+            return Ok(());
+        }
+        let Some(&slot) = self.tiled_geometry.get(&event.window) else {
+            return Ok(());
+        };
+        let actual = Rect {
+            x: event.x as i32,
+            y: event.y as i32,
+            width: event.width as u32,
+            height: event.height as u32,
+        };
+        if actual == slot {
+            return Ok(());
+        }
+        if let Some(snapped) = self.snapped_at.get(&event.window)
+            && snapped.elapsed() < SNAP_DEBOUNCE
+        {
+            return Ok(());
+        }
+
+        let changes = ConfigureWindowAux::new()
+            .x(slot.x)
+            .y(slot.y)
+            .width(slot.width)
+            .height(slot.height);
+        conn.configure_window(event.window, &changes)?;
+        self.snapped_at.insert(event.window, Instant::now());
+        Ok(())
+    }
+
+    // Keeps always-on-top windows above everything else after focus or layout changes
+    fn raise_above_windows<C: Connection>(
+        &self,
+        conn: &C,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let values = ConfigureWindowAux::new().stack_mode(StackMode::ABOVE);
+        let active_ws = &self.workspaces[self.active_workspace_idx];
+        // Floating windows sit above the tiled ones, and always-on-top windows above those
+        for &window in active_ws.floating.iter().chain(&active_ws.above) {
+            conn.configure_window(window, &values)?;
+        }
+        Ok(())
+    }
+
+    // Mod+Button1 / Mod+Button3 on a managed window start a move / resize. Also grabbed
+    // with NumLock and CapsLock so those don't get in the way.
+    //
+    // With click-to-focus every other click is grabbed too, freezing the pointer until
+    // start_drag has focused the window and replayed the click to it. That grab goes
+    // first, as the Mod grabs replace it for their own button/modifier combinations.
+    fn grab_buttons<C: Connection>(
+        &self,
+        conn: &C,
+        window: Window,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.focus_follows_mouse {
+            conn.grab_button(
+                false,
+                window,
+                EventMask::BUTTON_PRESS,
+                xproto::GrabMode::SYNC,
+                xproto::GrabMode::ASYNC,
+                x11rb::NONE,
+                x11rb::NONE,
+                ButtonIndex::ANY,
+                ModMask::ANY,
+            )?;
+        }
+
+        let mask = u16::from(self.mod_mask);
+        for button in [ButtonIndex::M1, ButtonIndex::M3] {
+            for ignored in [0, u16::from(ModMask::M2), u16::from(ModMask::LOCK)] {
+                conn.grab_button(
+                    false,
+                    window,
+                    EventMask::BUTTON_PRESS | EventMask::BUTTON_RELEASE | EventMask::POINTER_MOTION,
+                    xproto::GrabMode::ASYNC,
+                    xproto::GrabMode::ASYNC,
+                    x11rb::NONE,
+                    x11rb::NONE,
+                    button,
+                    ModMask::from(mask | ignored),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    // Starts dragging a floating window. Presses on tiled windows only focus them.
+    pub fn start_drag<C: Connection>(
+        &mut self,
+        conn: &C,
+        event: ButtonPressEvent,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let result = self.handle_button_press(conn, &event);
+        // With click-to-focus any press may come from the synchronous grab, which keeps
+        // the pointer frozen until it is released, whatever happened to the press
+        if !self.focus_follows_mouse {
+            conn.allow_events(xproto::Allow::REPLAY_POINTER, x11rb::CURRENT_TIME)?;
+        }
+        result
+    }
+
+    fn handle_button_press<C: Connection>(
+        &mut self,
+        conn: &C,
+        event: &ButtonPressEvent,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let window = event.event;
+        // A click without Mod comes from the click-to-focus grab
+        if u16::from(event.state) & u16::from(self.mod_mask) == 0 {
+            return self.focus_pointed_window(conn, window);
+        }
+        if !self.workspaces[self.active_workspace_idx]
+            .windows
+            .contains(&window)
+        {
+            return Ok(());
+        }
+        self.set_focus(conn, window)?;
+        if !self.workspaces[self.active_workspace_idx]
+            .floating
+            .contains(&window)
+        {
+            return Ok(());
+        }
+
+        let kind = match event.detail {
+            1 => DragKind::Move,
+            3 => DragKind::Resize,
+            _ => return Ok(()),
+        };
+        let geometry = conn.get_geometry(window)?.reply()?;
+        self.drag = Some(Drag {
+            window,
+            kind,
+            start_pointer: (event.root_x, event.root_y),
+            start_geometry: Rect {
+                x: geometry.x as i32,
+                y: geometry.y as i32,
+                width: geometry.width as u32,
+                height: geometry.height as u32,
+            },
+        });
+        Ok(())
+    }
+
+    pub fn handle_motion<C: Connection>(
+        &mut self,
+        conn: &C,
+        event: MotionNotifyEvent,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(drag) = &self.drag else {
+            return Ok(());
+        };
+        let dx = (event.root_x - drag.start_pointer.0) as i32;
+        let dy = (event.root_y - drag.start_pointer.1) as i32;
+        let start = drag.start_geometry;
+
+        let changes = match drag.kind {
+            DragKind::Move => ConfigureWindowAux::new().x(start.x + dx).y(start.y + dy),
+            DragKind::Resize => ConfigureWindowAux::new()
+                .width((start.width as i32 + dx).max(1) as u32)
+                .height((start.height as i32 + dy).max(1) as u32),
+        };
+        conn.configure_window(drag.window, &changes)?;
+        Ok(())
+    }
+
+    pub fn end_drag(&mut self) {
+        self.drag = None;
+    }
+
+    // Takes the focused window out of tiling, keeping its current geometry, or puts a
+    // floating window back into its tile
+    pub fn toggle_floating<C: Connection>(
+        &mut self,
+        conn: &C,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(window) = self.focused_window else {
+            return Ok(());
+        };
+        let active_ws = &mut self.workspaces[self.active_workspace_idx];
+        if let Some(pos) = active_ws.floating.iter().position(|&w| w == window) {
+            active_ws.floating.remove(pos);
+        } else if active_ws.windows.contains(&window) {
+            active_ws.floating.push(window);
+        }
+        self.refresh_layout(conn)
+    }
+
+    // Toggles the focused window between covering the whole screen and its tiled slot
+    pub fn toggle_fullscreen<C: Connection>(
+        &mut self,
+        conn: &C,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(window) = self.focused_window else {
+            return Ok(());
+        };
+        self.set_fullscreen(conn, window, self.fullscreen != Some(window))
+    }
+
+    // Only one window is fullscreen at a time; making another one fullscreen puts the
+    // previous one back in its tile. The windows' _NET_WM_STATE is kept in step.
+    fn set_fullscreen<C: Connection>(
+        &mut self,
+        conn: &C,
+        window: Window,
+        fullscreen: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if fullscreen == (self.fullscreen == Some(window)) {
+            return Ok(());
+        }
+        if fullscreen {
+            if let Some(previous) = self.fullscreen {
+                self.ewmh.set_fullscreen_state(conn, previous, false)?;
+            }
+            self.fullscreen = Some(window);
+        } else {
+            self.fullscreen = None;
+        }
+        self.ewmh.set_fullscreen_state(conn, window, fullscreen)?;
+        self.refresh_layout(conn)
+    }
+
+    pub fn toggle_above<C: Connection>(
+        &mut self,
+        conn: &C,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(window) = self.focused_window else {
+            return Ok(());
+        };
+        let idx = self.active_workspace_idx;
+        if !self.workspaces[idx].windows.contains(&window) {
+            return Ok(());
+        }
+        let above = !self.workspaces[idx].above.contains(&window);
+        self.set_above(conn, window, idx, above)
+    }
+
+    // Keeps a window on workspace `idx` above the others, or lets it sink back
+    fn set_above<C: Connection>(
+        &mut self,
+        conn: &C,
+        window: Window,
+        idx: usize,
+        above: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let ws = &mut self.workspaces[idx];
+        ws.above.retain(|&w| w != window);
+        if above {
+            ws.above.push(window);
+        }
+        self.raise_above_windows(conn)
+    }
+
+    pub fn promote_focused_to_master<C: Connection>(
+        &mut self,
+        conn: &C,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let active_ws = &mut self.workspaces[self.active_workspace_idx];
+        if let Some(focused) = self.focused_window
+            && active_ws.promote(focused, self.promote_when_master)
+        {
+            self.refresh_layout(conn)?;
+            self.update_bar(conn)?;
+        }
+        Ok(())
+    }
+
+    pub fn move_focused_to_master<C: Connection>(
+        &mut self,
+        conn: &C,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let active_ws = &mut self.workspaces[self.active_workspace_idx];
+        if active_ws.windows.len() < 2 {
+            return Ok(());
+        }
+
+        // Unlike promote, the rest of the stack keeps its relative order
+        if let Some(focused) = self.focused_window
+            && let Some(pos) = active_ws.windows.iter().position(|&w| w == focused)
+            && pos > 0
+        {
+            let window = active_ws.windows.remove(pos);
+            active_ws.windows.insert(0, window);
+            if pos < active_ws.split_history.len() {
+                let split = active_ws.split_history.remove(pos);
+                active_ws.split_history.insert(0, split);
+                let ratio = active_ws.split_ratios.remove(pos);
+                active_ws.split_ratios.insert(0, ratio);
+            }
+            self.refresh_layout(conn)?;
+            self.update_bar(conn)?;
+        }
+        Ok(())
+    }
+
+    // Reverses the window order of the active workspace
+    pub fn reverse_stack<C: Connection>(
+        &mut self,
+        conn: &C,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let active_ws = &mut self.workspaces[self.active_workspace_idx];
+        if active_ws.windows.len() < 2 {
+            return Ok(());
+        }
+
+        // Per-window split data stays parallel to the window list
+        active_ws.windows.reverse();
+        active_ws.split_history.reverse();
+        active_ws.split_ratios.reverse();
+
+        self.refresh_layout(conn)?;
+        self.update_bar(conn)?;
+        Ok(())
     }
 
-    pub fn promote_focused_to_master<C: Connection>(
+    // Swaps the layout positions of the focused and previously focused windows
+    pub fn swap_last_two<C: Connection>(
+        &mut self,
+        conn: &C,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let (Some(current), Some(previous)) = (self.focused_window, self.previous_focus) else {
+            return Ok(());
+        };
+        let active_ws = &mut self.workspaces[self.active_workspace_idx];
+        let current_pos = active_ws.windows.iter().position(|&w| w == current);
+        let previous_pos = active_ws.windows.iter().position(|&w| w == previous);
+
+        if let (Some(a), Some(b)) = (current_pos, previous_pos) {
+            // Per-window split data stays parallel to the window list
+            active_ws.windows.swap(a, b);
+            active_ws.split_history.swap(a, b);
+            active_ws.split_ratios.swap(a, b);
+            self.refresh_layout(conn)?;
+            self.update_bar(conn)?;
+        }
+        Ok(())
+    }
+
+    // Jumps from a stack window to the master, or from the master back to the
+    // stack window that was focused before the jump
+    pub fn toggle_master_focus<C: Connection>(
         &mut self,
         conn: &C,
     ) -> Result<(), Box<dyn std::error::Error>> {
         let active_ws = &mut self.workspaces[self.active_workspace_idx];
-        // Need at least 2 active windows to swap anything
         if active_ws.windows.len() < 2 {
             return Ok(());
         }
-
-        if let Some(focused) = self.focused_window {
-            if let Some(pos) = active_ws.windows.iter().position(|&w| w == focused) {
-                // If we are not Master (index 0), swap with Master
-                if pos > 0 {
-                    active_ws.windows.swap(0, pos);
-                } else {
-                    // If we are the Master, swap with the top of the stack (index 1).
-                    active_ws.windows.swap(0, 1);
-                }
-                self.refresh_layout(conn)?;
+        let master = active_ws.windows[0];
+
+        let target = match self.focused_window {
+            Some(focused) if focused == master => active_ws
+                .last_stack_focus
+                .filter(|w| active_ws.windows.contains(w))
+                .unwrap_or(active_ws.windows[1]),
+            Some(focused) => {
+                active_ws.last_stack_focus = Some(focused);
+                master
             }
+            None => master,
+        };
+        self.set_focus(conn, target)
+    }
+
+    // In Dwindle, split i separates window i from the region holding every later window,
+    // whatever its axis. The sibling across the focused window's split is the next window,
+    // which heads that region; the last window shares the final split with the one before.
+    pub fn focus_sibling<C: Connection>(
+        &mut self,
+        conn: &C,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let active_ws = &self.workspaces[self.active_workspace_idx];
+        let len = active_ws.windows.len();
+        if active_ws.layout != Layout::Dwindle || len < 2 {
+            return Ok(());
+        }
+
+        if let Some(focused) = self.focused_window
+            && let Some(pos) = active_ws.windows.iter().position(|&w| w == focused)
+        {
+            let sibling = if pos == len - 1 { pos - 1 } else { pos + 1 };
+            let target = active_ws.windows[sibling];
+            self.set_focus(conn, target)?;
         }
         Ok(())
     }
@@ -402,23 +1837,54 @@ impl WindowManager {
             return Ok(());
         }
 
-        if let Some(focused) = self.focused_window {
-            if let Some(pos) = active_ws.windows.iter().position(|&w| w == focused) {
-                // Calculate the new index based on direction
-                let new_pos = match dir {
-                    FocusDirection::Next => (pos + 1) % len, // Move Down (Wrap to top)
-                    FocusDirection::Prev => (pos + len - 1) % len, // Move Up (Wrap to bottom)
-                };
-                // Swap the windows in the vector
-                active_ws.windows.swap(pos, new_pos);
+        if let Some(focused) = self.focused_window
+            && let Some(pos) = active_ws.windows.iter().position(|&w| w == focused)
+        {
+            // Calculate the new index based on direction
+            let new_pos = match dir {
+                FocusDirection::Next => (pos + 1) % len, // Move Down (Wrap to top)
+                FocusDirection::Prev => (pos + len - 1) % len, // Move Up (Wrap to bottom)
+            };
+            // Swap the windows in the vector
+            active_ws.windows.swap(pos, new_pos);
+
+            // Refresh layout to reflect the new order
+            self.refresh_layout(conn)?;
+            self.update_bar(conn)?;
+        }
+        Ok(())
+    }
 
-                // Refresh layout to reflect the new order
-                self.refresh_layout(conn)?;
-            }
+    // Returns true when rwm should actually exit. With confirm_quit, the first press only
+    // arms the quit and a second press within QUIT_CONFIRM_TIMEOUT confirms it.
+    pub fn confirm_quit<C: Connection>(
+        &mut self,
+        conn: &C,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        if !self.confirm_quit || self.quit_pending() {
+            return Ok(true);
+        }
+        log::info!("Quit requested, press again to confirm");
+        self.pending_quit = Some(Instant::now());
+        self.update_bar(conn)?;
+        Ok(false)
+    }
+
+    pub fn cancel_quit<C: Connection>(
+        &mut self,
+        conn: &C,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if self.pending_quit.take().is_some() {
+            self.update_bar(conn)?;
         }
         Ok(())
     }
 
+    fn quit_pending(&self) -> bool {
+        self.pending_quit
+            .is_some_and(|requested| requested.elapsed() < QUIT_CONFIRM_TIMEOUT)
+    }
+
     pub fn kill_all_windows<C: Connection>(
         &self,
         conn: &C,
@@ -435,19 +1901,136 @@ impl WindowManager {
         Ok(())
     }
 
+    // Labels each visible window with its X id and workspace number, for scripting.
+    // Pressing it again, any other key, or the timer tick clears the labels.
+    pub fn show_window_ids<C: Connection>(
+        &mut self,
+        conn: &C,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.id_labels.is_empty() {
+            return self.clear_window_ids(conn);
+        }
+
+        let mut corners: Vec<(Window, i16, i16)> = self
+            .tiled_geometry
+            .iter()
+            .map(|(&window, geometry)| (window, geometry.x as i16, geometry.y as i16))
+            .collect();
+        // Floating windows aren't tiled, so ask the server where they are
+        for &ws_idx in &self.monitor_workspace {
+            for &window in &self.workspaces[ws_idx].floating {
+                if !self.tiled_geometry.contains_key(&window) {
+                    let geometry = conn.get_geometry(window)?.reply()?;
+                    corners.push((window, geometry.x, geometry.y));
+                }
+            }
+        }
+
+        for (window, x, y) in corners {
+            let Some(&ws_idx) = self.window_workspace.get(&window) else {
+                continue;
+            };
+            let text = format!("0x{:x} ws{}", window, ws_idx + 1);
+            let label = self.bar.create_label(conn, x + 4, y + 4, &text)?;
+            self.id_labels.insert(label, text);
+        }
+        self.id_labels_shown = Some(Instant::now());
+        Ok(())
+    }
+
+    pub fn clear_window_ids<C: Connection>(
+        &mut self,
+        conn: &C,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        for (label, _) in self.id_labels.drain() {
+            conn.destroy_window(label)?;
+        }
+        self.id_labels_shown = None;
+        Ok(())
+    }
+
+    pub fn toggle_title<C: Connection>(
+        &mut self,
+        conn: &C,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.bar.show_title = !self.bar.show_title;
+        self.update_bar(conn)
+    }
+
     pub fn toggle_bar<C: Connection>(
         &mut self,
         conn: &C,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        if self.current_top_gap > 0 {
-            self.current_top_gap = 0;
+        let refocus;
+        (self.bar_gap, refocus) = bar_toggle(self.bar_gap, self.bar.height(), self.focused_window);
+        if self.bar_gap == 0 {
             conn.unmap_window(self.bar.window)?;
         } else {
-            self.current_top_gap = 20;
             conn.map_window(self.bar.window)?;
             self.update_bar(conn)?;
         }
         self.refresh_layout(conn)?;
+        if let Some(win) = refocus {
+            self.set_focus(conn, win)?;
+        }
+        Ok(())
+    }
+
+    pub fn toggle_presentation<C: Connection>(
+        &mut self,
+        conn: &C,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(snapshot) = self.presentation_snapshot.take() {
+            log::info!("Leaving presentation mode");
+            if snapshot.bar_visible != (self.bar_gap > 0) {
+                self.toggle_bar(conn)?;
+            }
+            self.workspaces[snapshot.workspace].layout = snapshot.layout;
+            self.set_focus_follows_mouse(conn, snapshot.focus_follows_mouse)?;
+        } else {
+            log::info!("Entering presentation mode");
+            let bar_visible = self.bar_gap > 0;
+            self.presentation_snapshot = Some(PresentationSnapshot {
+                workspace: self.active_workspace_idx,
+                layout: self.workspaces[self.active_workspace_idx].layout,
+                bar_visible,
+                focus_follows_mouse: self.focus_follows_mouse,
+            });
+            if self.presentation.hide_bar && bar_visible {
+                self.toggle_bar(conn)?;
+            }
+            if self.presentation.monocle {
+                self.workspaces[self.active_workspace_idx].layout = Layout::Monocle;
+            }
+            if self.presentation.click_to_focus {
+                self.set_focus_follows_mouse(conn, false)?;
+            }
+        }
+
+        if let Some(win) = self.focused_window {
+            self.set_focus(conn, win)?;
+        }
+        self.update_bar(conn)?;
+        self.refresh_layout(conn)?;
+        Ok(())
+    }
+
+    // Switches between focus-follows-mouse and click-to-focus, which grab the buttons
+    // of every managed window differently
+    fn set_focus_follows_mouse<C: Connection>(
+        &mut self,
+        conn: &C,
+        on: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if self.focus_follows_mouse == on {
+            return Ok(());
+        }
+        self.focus_follows_mouse = on;
+        let windows: Vec<Window> = self.window_workspace.keys().copied().collect();
+        for window in windows {
+            conn.ungrab_button(ButtonIndex::ANY, window, ModMask::ANY)?;
+            self.grab_buttons(conn, window)?;
+        }
         Ok(())
     }
 
@@ -455,9 +2038,96 @@ impl WindowManager {
         &mut self,
         conn: &C,
         x: i16,
+        button: u8,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        // Scrolling over the layout symbol cycles layouts: up for next, down for previous
+        if self.bar.is_over_layout(x) {
+            match button {
+                4 => return self.cycle_layout(conn),
+                5 => return self.cycle_layout_back(conn),
+                _ => {}
+            }
+        }
         if let Some(ws_idx) = self.bar.get_clicked_workspace(x) {
             self.switch_workspace(conn, ws_idx)?;
+        } else if let Some(window) = self.bar.get_clicked_tab(x).filter(|_| button == 1) {
+            self.set_focus(conn, window)?;
+        }
+        Ok(())
+    }
+
+    // Maps a window matching external_bar_class without tiling it, and grabs the scroll
+    // wheel on it. Returns false if the window isn't the external bar.
+    fn adopt_external_bar<C: Connection>(
+        &mut self,
+        conn: &C,
+        window: Window,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let Some(bar_class) = &self.external_bar_class else {
+            return Ok(false);
+        };
+        if !window_class(conn, window)?.iter().any(|c| c == bar_class) {
+            return Ok(false);
+        }
+
+        for button in [ButtonIndex::M4, ButtonIndex::M5] {
+            conn.grab_button(
+                false,
+                window,
+                EventMask::BUTTON_PRESS,
+                xproto::GrabMode::ASYNC,
+                xproto::GrabMode::ASYNC,
+                x11rb::NONE,
+                x11rb::NONE,
+                button,
+                xproto::ModMask::ANY,
+            )?;
+        }
+        let changes = ChangeWindowAttributesAux::new().event_mask(EventMask::STRUCTURE_NOTIFY);
+        conn.change_window_attributes(window, &changes)?;
+        conn.map_window(window)?;
+
+        self.external_bar = Some(window);
+        log::info!("Adopted external bar {}", window);
+        Ok(true)
+    }
+
+    // Maps a _NET_WM_WINDOW_TYPE_DOCK window (polybar and the like) without managing it,
+    // and keeps the space its strut reserves clear of tiled windows. Returns false if
+    // the window isn't a dock.
+    fn adopt_dock<C: Connection>(
+        &mut self,
+        conn: &C,
+        window: Window,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        if !self.ewmh.is_dock(conn, window)? {
+            return Ok(false);
+        }
+        let strut = self.ewmh.strut(conn, window)?;
+        self.docks.insert(window, strut);
+        // Watch for strut changes and for the dock unmapping itself
+        let changes = ChangeWindowAttributesAux::new()
+            .event_mask(EventMask::STRUCTURE_NOTIFY | EventMask::PROPERTY_CHANGE);
+        conn.change_window_attributes(window, &changes)?;
+        conn.map_window(window)?;
+        log::info!("Adopted dock {}", window);
+        self.refresh_layout(conn)?;
+        Ok(true)
+    }
+
+    // Scroll up goes to the previous workspace, scroll down to the next
+    pub fn handle_external_bar_scroll<C: Connection>(
+        &mut self,
+        conn: &C,
+        button: u8,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let dir = match button {
+            4 => FocusDirection::Prev,
+            5 => FocusDirection::Next,
+            _ => return Ok(()),
+        };
+        if let Some(idx) = self.adjacent_workspace(dir) {
+            self.switch_workspace(conn, idx)?;
         }
         Ok(())
     }
@@ -469,10 +2139,10 @@ impl WindowManager {
     ) -> Result<(), Box<dyn std::error::Error>> {
         self.pending_split = axis;
 
-        if let Some(ws) = self.workspaces.get_mut(self.active_workspace_idx) {
-            if let Some(last_split) = ws.split_history.last_mut() {
-                *last_split = axis;
-            }
+        if let Some(ws) = self.workspaces.get_mut(self.active_workspace_idx)
+            && let Some(last_split) = ws.split_history.last_mut()
+        {
+            *last_split = axis;
         }
 
         log::info!("Next window will split: {:?}", axis);
@@ -482,17 +2152,83 @@ impl WindowManager {
         Ok(())
     }
 
+    // Grows (positive delta) or shrinks the focused window's share of its Dwindle split
+    pub fn adjust_dwindle_ratio<C: Connection>(
+        &mut self,
+        conn: &C,
+        delta: f32,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let active_ws = &mut self.workspaces[self.active_workspace_idx];
+        if active_ws.layout != Layout::Dwindle || active_ws.windows.len() < 2 {
+            return Ok(());
+        }
+        let Some(pos) = self
+            .focused_window
+            .and_then(|w| active_ws.windows.iter().position(|&win| win == w))
+        else {
+            return Ok(());
+        };
+
+        // The last window has no split of its own; it takes what the previous split leaves
+        let (split, delta) = if pos == active_ws.windows.len() - 1 {
+            (pos - 1, -delta)
+        } else {
+            (pos, delta)
+        };
+        if let Some(ratio) = active_ws.split_ratios.get_mut(split) {
+            *ratio = (*ratio + delta).clamp(layout::MIN_SPLIT_RATIO, layout::MAX_SPLIT_RATIO);
+            self.refresh_layout(conn)?;
+        }
+        Ok(())
+    }
+
+    pub fn adjust_master_ratio<C: Connection>(
+        &mut self,
+        conn: &C,
+        delta: f32,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let active_ws = &mut self.workspaces[self.active_workspace_idx];
+        if !matches!(
+            active_ws.layout,
+            Layout::MasterStack | Layout::CenteredMaster
+        ) {
+            return Ok(());
+        }
+        active_ws.master_ratio = (active_ws.master_ratio + delta)
+            .clamp(layout::MIN_MASTER_RATIO, layout::MAX_MASTER_RATIO);
+        self.refresh_layout(conn)?;
+        self.update_bar(conn)?;
+        Ok(())
+    }
+
     pub fn setup_cursor(
         conn: &impl Connection,
         screen: &xproto::Screen,
+        cursor_name: &str,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        let glyph = cursor_glyph(cursor_name).unwrap_or_else(|| {
+            log::warn!("Unknown cursor '{}', using left_ptr", cursor_name);
+            LEFT_PTR_GLYPH
+        });
+
         let font_id = conn.generate_id()?;
         conn.open_font(font_id, b"cursor")?;
 
         let cursor_id = conn.generate_id()?;
 
+        // The mask for each glyph in the cursor font is the glyph that follows it
         conn.create_glyph_cursor(
-            cursor_id, font_id, font_id, 68, 69, 0, 0, 0, 65535, 65535, 65535,
+            cursor_id,
+            font_id,
+            font_id,
+            glyph,
+            glyph + 1,
+            0,
+            0,
+            0,
+            65535,
+            65535,
+            65535,
         )?;
 
         let changes = xproto::ChangeWindowAttributesAux::new().cursor(cursor_id);
@@ -501,3 +2237,410 @@ impl WindowManager {
         Ok(())
     }
 }
+
+// Reads WM_CLASS, which holds the instance and class names as two NUL-terminated strings
+fn window_class<C: Connection>(
+    conn: &C,
+    window: Window,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let prop = conn
+        .get_property(false, window, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 1024)?
+        .reply()?;
+    Ok(prop
+        .value
+        .split(|&b| b == 0)
+        .filter(|part| !part.is_empty())
+        .map(|part| String::from_utf8_lossy(part).into_owned())
+        .collect())
+}
+
+// Bar gap after toggling the bar, and the window to focus again once the workspace is
+// re-tiled. Re-tiling can leave the focused window below its neighbours; focus itself
+// never moves.
+fn bar_toggle(bar_gap: u16, shown_gap: u16, focused: Option<Window>) -> (u16, Option<Window>) {
+    let bar_gap = if bar_gap > 0 { 0 } else { shown_gap };
+    (bar_gap, focused)
+}
+
+// Windows join and leave workspaces only through add_window and take_window, which
+// keep the window -> workspace index in step with the workspaces' window lists.
+// Positions are not indexed: a window's place in its list is the tiling order, which
+// most actions reorder, so it is found by scanning that one workspace.
+fn add_window(
+    workspaces: &mut [Workspace],
+    index: &mut HashMap<Window, usize>,
+    window: Window,
+    idx: usize,
+    entry: WindowEntry,
+) {
+    workspaces[idx].insert_window(window, entry);
+    index.insert(window, idx);
+}
+
+fn take_window(
+    workspaces: &mut [Workspace],
+    index: &mut HashMap<Window, usize>,
+    window: Window,
+) -> Option<(usize, WindowEntry)> {
+    let idx = index.remove(&window)?;
+    let entry = workspaces[idx].remove_window(window)?;
+    Some((idx, entry))
+}
+
+// Default layout of each workspace from workspace_layouts. Config is 1-based,
+// internal is 0-based.
+fn default_layouts(config: &Config, workspace_count: usize) -> Vec<Layout> {
+    let mut layouts = vec![Layout::MasterStack; workspace_count];
+    for (key, &layout) in &config.workspace_layouts {
+        match key.parse::<usize>() {
+            Ok(n) if (1..=workspace_count).contains(&n) => layouts[n - 1] = layout,
+            _ => log::warn!("Ignoring layout for unknown workspace '{}'", key),
+        }
+    }
+    layouts
+}
+
+// workspace_count from the config; there is always at least one workspace
+fn workspace_count(config: &Config) -> usize {
+    config.workspace_count.max(1)
+}
+
+// Workspace names for pagers: the bar's workspace icons, or the workspace number where
+// there aren't enough icons
+fn desktop_names(config: &Config, workspace_count: usize) -> Vec<String> {
+    (0..workspace_count)
+        .map(|i| {
+            config
+                .bar
+                .workspace_icons
+                .get(i)
+                .cloned()
+                .unwrap_or_else(|| (i + 1).to_string())
+        })
+        .collect()
+}
+
+// Workspace index -> command for each on_enter hook. Config is 1-based, internal is 0-based.
+fn on_enter_hooks(config: &Config, workspace_count: usize) -> HashMap<usize, String> {
+    let mut on_enter = HashMap::new();
+    for (key, command) in &config.on_enter {
+        match key.parse::<usize>() {
+            Ok(n) if (1..=workspace_count).contains(&n) => {
+                on_enter.insert(n - 1, command.clone());
+            }
+            _ => log::warn!("Ignoring on_enter hook for unknown workspace '{}'", key),
+        }
+    }
+    on_enter
+}
+
+// The configured corner radius, or 0 when the server can't shape windows
+fn corner_radius<C: Connection>(
+    conn: &C,
+    config: &Config,
+) -> Result<u16, Box<dyn std::error::Error>> {
+    let shape_available = conn
+        .extension_information(x11rb::protocol::shape::X11_EXTENSION_NAME)?
+        .is_some();
+    if config.corner_radius > 0 && !shape_available {
+        log::warn!("Shape extension unavailable, windows will not have rounded corners");
+        return Ok(0);
+    }
+    Ok(config.corner_radius)
+}
+
+// Focused and unfocused border pixel values
+fn border_pixels(bar: &Bar, config: &Config) -> (u32, u32) {
+    let focused = config::parse_color(&config.focused_border_color).unwrap_or_else(|| {
+        log::warn!(
+            "Invalid focused_border_color '{}'",
+            config.focused_border_color
+        );
+        0x005577
+    });
+    let unfocused = config::parse_color(&config.unfocused_border_color).unwrap_or_else(|| {
+        log::warn!(
+            "Invalid unfocused_border_color '{}'",
+            config.unfocused_border_color
+        );
+        0x444444
+    });
+    (bar.pixel(focused), bar.pixel(unfocused))
+}
+
+// Geometry of each enabled RandR CRTC, left to right. Mirrored outputs count once.
+// Without RandR the whole screen is one monitor.
+fn query_monitors<C: Connection>(
+    conn: &C,
+    screen: &Screen,
+) -> Result<Vec<Rect>, Box<dyn std::error::Error>> {
+    let whole = Rect {
+        x: 0,
+        y: 0,
+        width: screen.width_in_pixels as u32,
+        height: screen.height_in_pixels as u32,
+    };
+    if conn
+        .extension_information(randr::X11_EXTENSION_NAME)?
+        .is_none()
+    {
+        log::warn!("RandR unavailable, treating the screen as one monitor");
+        return Ok(vec![whole]);
+    }
+
+    let resources = conn
+        .randr_get_screen_resources_current(screen.root)?
+        .reply()?;
+    let mut monitors = Vec::new();
+    for crtc in resources.crtcs {
+        let info = conn
+            .randr_get_crtc_info(crtc, resources.config_timestamp)?
+            .reply()?;
+        let rect = Rect {
+            x: info.x as i32,
+            y: info.y as i32,
+            width: info.width as u32,
+            height: info.height as u32,
+        };
+        // Disabled CRTCs have no mode
+        if info.mode != x11rb::NONE && rect.width > 0 && !monitors.contains(&rect) {
+            monitors.push(rect);
+        }
+    }
+    monitors.sort_by_key(|m| (m.x, m.y));
+    if monitors.is_empty() {
+        monitors.push(whole);
+    }
+    Ok(monitors)
+}
+
+// The candidate nearest to `from` in a direction, by the distance between centers.
+// Only windows centered past `from`'s edge count, and those overlapping it across the
+// direction of travel win over closer ones that don't, so Left from a stack window
+// picks the master beside it and Up from the master finds nothing.
+fn nearest_in_direction(
+    from: Rect,
+    candidates: &[(Window, Rect)],
+    dir: &Direction,
+) -> Option<Window> {
+    // Centers are doubled to stay in whole pixels
+    let center = |r: Rect| (r.x * 2 + r.width as i32, r.y * 2 + r.height as i32);
+    let (from_x, from_y) = center(from);
+    let (left, top) = (from.x * 2, from.y * 2);
+    let (right, bottom) = (left + from.width as i32 * 2, top + from.height as i32 * 2);
+
+    let mut best: Option<((bool, i32, i32), Window)> = None;
+    for &(window, to) in candidates {
+        let (to_x, to_y) = center(to);
+        // Distance along the direction of travel, and the offset across it
+        let (along, across, beyond) = match dir {
+            Direction::Left => (from_x - to_x, to_y - from_y, to_x < left),
+            Direction::Right => (to_x - from_x, to_y - from_y, to_x > right),
+            Direction::Up => (from_y - to_y, to_x - from_x, to_y < top),
+            Direction::Down => (to_y - from_y, to_x - from_x, to_y > bottom),
+        };
+        if !beyond {
+            continue;
+        }
+        let overlaps = match dir {
+            Direction::Left | Direction::Right => {
+                to.y < from.y + from.height as i32 && from.y < to.y + to.height as i32
+            }
+            Direction::Up | Direction::Down => {
+                to.x < from.x + from.width as i32 && from.x < to.x + to.width as i32
+            }
+        };
+        let score = (!overlaps, along, across.abs());
+        if best.is_none_or(|(best_score, _)| score < best_score) {
+            best = Some((score, window));
+        }
+    }
+    best.map(|(_, window)| window)
+}
+
+// Maps an X cursor font name (see X11/cursorfont.h) to its glyph index
+fn cursor_glyph(name: &str) -> Option<u16> {
+    let glyph = match name {
+        "X_cursor" => 0,
+        "arrow" => 2,
+        "crosshair" => 34,
+        "fleur" => 52,
+        "hand1" => 58,
+        "hand2" => 60,
+        "left_ptr" => LEFT_PTR_GLYPH,
+        "pirate" => 88,
+        "question_arrow" => 92,
+        "sizing" => 120,
+        "top_left_arrow" => 132,
+        "watch" => 150,
+        "xterm" => 152,
+        _ => return None,
+    };
+    Some(glyph)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, width: u32, height: u32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    // MasterStack with three windows: master 1 on the left, 2 above 3 on the right
+    fn master_stack() -> Vec<(Window, Rect)> {
+        vec![
+            (1, rect(0, 0, 500, 600)),
+            (2, rect(500, 0, 500, 300)),
+            (3, rect(500, 300, 500, 300)),
+        ]
+    }
+
+    fn nearest(from: Window, dir: Direction) -> Option<Window> {
+        let windows = master_stack();
+        let from_rect = windows.iter().find(|(w, _)| *w == from).unwrap().1;
+        let others: Vec<_> = windows.into_iter().filter(|(w, _)| *w != from).collect();
+        nearest_in_direction(from_rect, &others, &dir)
+    }
+
+    #[test]
+    fn focus_survives_a_bar_toggle() {
+        let (hidden, refocus) = bar_toggle(24, 24, Some(7));
+        assert_eq!((hidden, refocus), (0, Some(7)));
+        let (shown, refocus) = bar_toggle(hidden, 24, refocus);
+        assert_eq!((shown, refocus), (24, Some(7)));
+        assert_eq!(bar_toggle(24, 24, None), (0, None));
+    }
+
+    #[test]
+    fn workspace_count_is_at_least_one() {
+        let mut config = Config::default();
+        assert_eq!(workspace_count(&config), 9);
+        config.workspace_count = 5;
+        assert_eq!(workspace_count(&config), 5);
+        config.workspace_count = 0;
+        assert_eq!(workspace_count(&config), 1);
+    }
+
+    #[test]
+    fn workspace_layouts_set_default_layouts() {
+        let config: Config = toml::from_str(
+            r#"
+            [workspace_layouts]
+            2 = "monocle"
+            4 = "centered_master"
+            0 = "grid"
+            12 = "grid"
+            web = "tabbed"
+            "#,
+        )
+        .unwrap();
+        let layouts = default_layouts(&config, 4);
+        assert_eq!(
+            layouts,
+            vec![
+                Layout::MasterStack,
+                Layout::Monocle,
+                Layout::MasterStack,
+                Layout::CenteredMaster,
+            ]
+        );
+        assert!(toml::from_str::<Config>("[workspace_layouts]\n1 = \"stacked\"").is_err());
+    }
+
+    #[test]
+    fn desktop_names_follow_the_workspace_count() {
+        let mut config = Config::default();
+        config.bar.workspace_icons = vec!["web".into(), "code".into()];
+        assert_eq!(desktop_names(&config, 4), ["web", "code", "3", "4"]);
+        assert_eq!(desktop_names(&config, 1), ["web"]);
+    }
+
+    #[test]
+    fn direction_moves_between_master_and_stack() {
+        assert_eq!(nearest(1, Direction::Right), Some(2));
+        assert_eq!(nearest(3, Direction::Left), Some(1));
+        assert_eq!(nearest(2, Direction::Down), Some(3));
+        assert_eq!(nearest(3, Direction::Up), Some(2));
+    }
+
+    #[test]
+    fn nothing_past_the_edge() {
+        assert_eq!(nearest(1, Direction::Left), None);
+        assert_eq!(nearest(1, Direction::Up), None);
+        assert_eq!(nearest(2, Direction::Right), None);
+        assert_eq!(nearest(3, Direction::Down), None);
+    }
+
+    #[test]
+    fn overlapping_windows_beat_closer_diagonal_ones() {
+        let from = rect(0, 0, 100, 100);
+        let candidates = [
+            // Closer, but entirely below the focused window's row
+            (1, rect(100, 110, 50, 50)),
+            // Further right, level with it
+            (2, rect(300, 0, 100, 100)),
+        ];
+        assert_eq!(
+            nearest_in_direction(from, &candidates, &Direction::Right),
+            Some(2)
+        );
+    }
+
+    // Every indexed window is in its workspace's list, and every listed window is indexed
+    fn assert_index_consistent(workspaces: &[Workspace], index: &HashMap<Window, usize>) {
+        let listed: usize = workspaces.iter().map(|ws| ws.windows.len()).sum();
+        assert_eq!(listed, index.len());
+        for (&window, &idx) in index {
+            assert!(workspaces[idx].windows.contains(&window), "{}", window);
+        }
+    }
+
+    #[test]
+    fn window_index_follows_adds_moves_and_removes() {
+        let mut workspaces: Vec<Workspace> = (0..9)
+            .map(|_| Workspace::new(0.5, Layout::MasterStack))
+            .collect();
+        let mut index = HashMap::new();
+        let entry = || WindowEntry {
+            split: Some((SplitAxis::Vertical, 0.5)),
+            above: false,
+            floating: false,
+        };
+
+        for window in 0..500 {
+            add_window(
+                &mut workspaces,
+                &mut index,
+                window,
+                window as usize % 9,
+                entry(),
+            );
+        }
+        assert_index_consistent(&workspaces, &index);
+
+        // Move every third window one workspace along, as MoveToWorkspace does
+        for window in (0..500).step_by(3) {
+            let (idx, entry) = take_window(&mut workspaces, &mut index, window).unwrap();
+            add_window(&mut workspaces, &mut index, window, (idx + 1) % 9, entry);
+            assert_eq!(index[&window], (idx + 1) % 9);
+        }
+        assert_index_consistent(&workspaces, &index);
+
+        for window in (0..500).step_by(2) {
+            assert!(take_window(&mut workspaces, &mut index, window).is_some());
+        }
+        assert_index_consistent(&workspaces, &index);
+        assert_eq!(index.len(), 250);
+
+        // Unmanaged and already removed windows are left alone
+        assert!(take_window(&mut workspaces, &mut index, 0).is_none());
+        assert!(take_window(&mut workspaces, &mut index, 1000).is_none());
+        assert_index_consistent(&workspaces, &index);
+    }
+}
@@ -1,4 +1,6 @@
+use crate::config::PromoteWhenMaster;
 use crate::layout::Layout;
+use crate::state::FocusDirection;
 use x11rb::protocol::xproto::Window;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -7,18 +9,262 @@ pub enum SplitAxis {
     Vertical,
 }
 
+// What a workspace tracks about one of its windows besides its place in the stack
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WindowEntry {
+    pub split: Option<(SplitAxis, f32)>, // Dwindle split axis and ratio
+    pub above: bool,
+    pub floating: bool,
+}
+
 pub struct Workspace {
     pub windows: Vec<Window>,
     pub layout: Layout,
+    pub default_layout: Layout, // Restored by ResetLayout
     pub split_history: Vec<SplitAxis>,
+    pub split_ratios: Vec<f32>, // Dwindle split share per window, parallel to split_history
+    pub above: Vec<Window>,     // Always-on-top windows, raised in this order
+    pub last_stack_focus: Option<Window>, // Where ToggleMasterFocus returns to
+    pub floating: Vec<Window>,  // Left out of tiling, stacked above the tiled windows
+    pub master_ratio: f32,      // MasterStack master column share, adjusted by ResizeMaster
+    pub stacking: Vec<Window>,  // Bottom-to-top order when last left, if preserve_stacking is on
 }
 
 impl Workspace {
-    pub fn new() -> Self {
+    pub fn new(master_ratio: f32, layout: Layout) -> Self {
         Self {
             windows: Vec::new(),
-            layout: Layout::MasterStack, // Default layout
+            layout,
+            default_layout: layout,
             split_history: Vec::new(),
+            split_ratios: Vec::new(),
+            above: Vec::new(),
+            last_stack_focus: None,
+            floating: Vec::new(),
+            master_ratio,
+            stacking: Vec::new(),
         }
     }
+
+    // Takes a window out of the workspace along with every reference to it, keeping the
+    // split data parallel to the window list. None if the window isn't here.
+    pub fn remove_window(&mut self, window: Window) -> Option<WindowEntry> {
+        let pos = self.windows.iter().position(|&w| w == window)?;
+        self.windows.remove(pos);
+        let split = (pos < self.split_history.len()).then(|| {
+            (
+                self.split_history.remove(pos),
+                self.split_ratios.remove(pos),
+            )
+        });
+        let above = self.above.contains(&window);
+        self.above.retain(|&w| w != window);
+        let floating = self.floating.contains(&window);
+        self.floating.retain(|&w| w != window);
+        self.stacking.retain(|&w| w != window);
+        if self.last_stack_focus == Some(window) {
+            self.last_stack_focus = None;
+        }
+        Some(WindowEntry {
+            split,
+            above,
+            floating,
+        })
+    }
+
+    // Adds a window at the end of the stack with the state it had on its old workspace
+    pub fn insert_window(&mut self, window: Window, entry: WindowEntry) {
+        self.windows.push(window);
+        if let Some((axis, ratio)) = entry.split {
+            self.split_history.push(axis);
+            self.split_ratios.push(ratio);
+        }
+        if entry.above {
+            self.above.push(window);
+        }
+        if entry.floating {
+            self.floating.push(window);
+        }
+    }
+
+    // Moves a stack window into the master slot, swapping it with the master. For the
+    // master itself, `when_master` decides. Returns whether anything moved. Split data
+    // moves with its window in every mode.
+    pub fn promote(&mut self, window: Window, when_master: PromoteWhenMaster) -> bool {
+        if self.windows.len() < 2 {
+            return false;
+        }
+        match self.windows.iter().position(|&w| w == window) {
+            Some(0) => match when_master {
+                PromoteWhenMaster::SwapSecond => self.swap_windows(0, 1),
+                PromoteWhenMaster::Noop => return false,
+                PromoteWhenMaster::Rotate => {
+                    self.windows.rotate_left(1);
+                    self.split_history.rotate_left(1);
+                    self.split_ratios.rotate_left(1);
+                }
+            },
+            Some(pos) => self.swap_windows(0, pos),
+            None => return false,
+        }
+        true
+    }
+
+    // Swaps two windows along with their split data
+    fn swap_windows(&mut self, a: usize, b: usize) {
+        self.windows.swap(a, b);
+        if a.max(b) < self.split_history.len() {
+            self.split_history.swap(a, b);
+            self.split_ratios.swap(a, b);
+        }
+    }
+
+    // The window FocusNext/FocusPrev moves to, wrapping at the ends. Starts at the first
+    // window when the focused one isn't on this workspace.
+    pub fn cycle_target(&self, focused: Option<Window>, dir: &FocusDirection) -> Option<Window> {
+        let len = self.windows.len();
+        if len == 0 {
+            return None;
+        }
+        let next = match focused.and_then(|f| self.windows.iter().position(|&w| w == f)) {
+            Some(i) => match dir {
+                FocusDirection::Next => (i + 1) % len,
+                FocusDirection::Prev => (i + len - 1) % len,
+            },
+            None => 0,
+        };
+        Some(self.windows[next])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A workspace holding the given windows, each with its own split ratio
+    fn workspace_with(windows: &[Window]) -> Workspace {
+        let mut ws = Workspace::new(0.5, Layout::MasterStack);
+        for (i, &window) in windows.iter().enumerate() {
+            ws.windows.push(window);
+            ws.split_history.push(SplitAxis::Vertical);
+            ws.split_ratios.push(0.1 * (i + 1) as f32);
+        }
+        ws
+    }
+
+    #[test]
+    fn removing_an_unfocused_window_keeps_cycling_correct() {
+        // Three windows with the middle one focused; the first is destroyed
+        let mut ws = workspace_with(&[1, 2, 3]);
+        let focused = Some(2);
+        assert!(ws.remove_window(1).is_some());
+
+        assert_eq!(ws.windows, [2, 3]);
+        assert_eq!(ws.split_ratios, [0.2, 0.3]);
+        assert_eq!(ws.cycle_target(focused, &FocusDirection::Next), Some(3));
+        assert_eq!(ws.cycle_target(focused, &FocusDirection::Prev), Some(3));
+        assert_eq!(ws.cycle_target(Some(3), &FocusDirection::Next), Some(2));
+    }
+
+    #[test]
+    fn removing_a_window_drops_every_reference() {
+        let mut ws = workspace_with(&[1, 2, 3]);
+        ws.above.push(2);
+        ws.floating.push(2);
+        ws.stacking = vec![3, 2, 1];
+        ws.last_stack_focus = Some(2);
+
+        let entry = ws.remove_window(2);
+        assert_eq!(
+            entry,
+            Some(WindowEntry {
+                split: Some((SplitAxis::Vertical, 0.2)),
+                above: true,
+                floating: true,
+            })
+        );
+        assert!(ws.above.is_empty());
+        assert!(ws.floating.is_empty());
+        assert_eq!(ws.stacking, [3, 1]);
+        assert_eq!(ws.last_stack_focus, None);
+        assert_eq!(ws.remove_window(2), None);
+    }
+
+    #[test]
+    fn moving_a_window_takes_its_state_along() {
+        // Window 2 is kept above, floating and has a stacking slot; all of it moves
+        let mut source = workspace_with(&[1, 2, 3]);
+        source.above.push(2);
+        source.floating.push(2);
+        source.stacking = vec![1, 2, 3];
+        let mut target = workspace_with(&[4]);
+
+        let entry = source.remove_window(2).unwrap();
+        target.insert_window(2, entry);
+
+        assert!(!source.windows.contains(&2));
+        assert!(!source.above.contains(&2));
+        assert!(!source.floating.contains(&2));
+        assert!(!source.stacking.contains(&2));
+        assert_eq!(source.split_ratios, [0.1, 0.3]);
+
+        assert_eq!(target.windows, [4, 2]);
+        assert_eq!(target.above, [2]);
+        assert_eq!(target.floating, [2]);
+        assert_eq!(target.split_ratios, [0.1, 0.2]);
+    }
+
+    #[test]
+    fn promoting_a_stack_window_swaps_it_with_the_master() {
+        let mut ws = workspace_with(&[1, 2, 3]);
+        assert!(ws.promote(3, PromoteWhenMaster::Noop));
+        assert_eq!(ws.windows, [3, 2, 1]);
+        assert_eq!(ws.split_ratios, [0.3, 0.2, 0.1]);
+    }
+
+    #[test]
+    fn promoting_the_master_swap_second() {
+        let mut ws = workspace_with(&[1, 2, 3]);
+        assert!(ws.promote(1, PromoteWhenMaster::SwapSecond));
+        assert_eq!(ws.windows, [2, 1, 3]);
+        assert_eq!(ws.split_ratios, [0.2, 0.1, 0.3]);
+    }
+
+    #[test]
+    fn promoting_the_master_noop() {
+        let mut ws = workspace_with(&[1, 2, 3]);
+        assert!(!ws.promote(1, PromoteWhenMaster::Noop));
+        assert_eq!(ws.windows, [1, 2, 3]);
+        assert_eq!(ws.split_ratios, [0.1, 0.2, 0.3]);
+    }
+
+    #[test]
+    fn promoting_the_master_rotate() {
+        let mut ws = workspace_with(&[1, 2, 3]);
+        assert!(ws.promote(1, PromoteWhenMaster::Rotate));
+        assert_eq!(ws.windows, [2, 3, 1]);
+        assert_eq!(ws.split_ratios, [0.2, 0.3, 0.1]);
+    }
+
+    #[test]
+    fn promoting_needs_two_windows_on_the_workspace() {
+        let mut ws = workspace_with(&[1]);
+        assert!(!ws.promote(1, PromoteWhenMaster::SwapSecond));
+        let mut ws = workspace_with(&[1, 2]);
+        assert!(!ws.promote(9, PromoteWhenMaster::SwapSecond));
+        assert_eq!(ws.windows, [1, 2]);
+    }
+
+    #[test]
+    fn cycling_wraps_and_starts_at_the_first_window() {
+        let ws = workspace_with(&[1, 2, 3]);
+        assert_eq!(ws.cycle_target(Some(3), &FocusDirection::Next), Some(1));
+        assert_eq!(ws.cycle_target(Some(1), &FocusDirection::Prev), Some(3));
+        assert_eq!(ws.cycle_target(None, &FocusDirection::Prev), Some(1));
+        assert_eq!(ws.cycle_target(Some(9), &FocusDirection::Next), Some(1));
+        assert_eq!(
+            Workspace::new(0.5, Layout::MasterStack).cycle_target(None, &FocusDirection::Next),
+            None
+        );
+    }
 }